@@ -0,0 +1,90 @@
+use anubis::{
+    common::Block,
+    config::LanguageConfig,
+    db::AnubisDatabase,
+    parser_core::{file_parser, SourceIndex},
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use nom::Parser;
+use std::path::Path;
+
+fn rust_config() -> LanguageConfig {
+    LanguageConfig {
+        language: "rust".to_string(),
+        anubis_character: "@".to_string(),
+        multiline_start: "/*".to_string(),
+        multiline_end: "*/".to_string(),
+        single_line_prefixes: vec!["//".to_string()],
+        decoration_prefixes: vec!["*".to_string()],
+        ..Default::default()
+    }
+}
+
+// A source file mixing plain code, multiline blocks, line comment blocks and nested blocks
+fn generate_file(file: usize, blocks: usize) -> String {
+    let mut source = String::new();
+    for block in 0..blocks {
+        let name = format!("File {file} Block {block}");
+        source.push_str(&format!(
+            "fn unrelated_{block}(x: u32) -> u32 {{\n    x * 2 // not a block\n}}\n\n\
+             /*@[{name}|Impl]\n\
+             # {name}\n\
+             Some prose describing the code below, with a link to {{File {file} Block 0}}\n\
+             and an escaped \\{{brace\\}} as well as **emphasis** and `inline code`.\n\
+             */\n\
+             fn documented_{block}(values: &[u32]) -> u32 {{\n    values.iter().sum()\n}}\n\
+             /*@[{name} Child|Impl]\n\
+             Nested prose.\n\
+             */\n\
+             fn child_{block}() {{}}\n\
+             /*@*/\n\
+             /*@*/\n\n\
+             //@[{name} Lines|Impl]\n\
+             // ## Line comments\n\
+             // Prose written with line comments, embedding {{{{{name}}}}}\n\
+             fn lines_{block}() {{}}\n\
+             //@\n\n"
+        ));
+    }
+    source
+}
+
+fn parse_file<'a>(config: &'a LanguageConfig, source_index: &'a SourceIndex<'a>) -> Vec<Block<'a>> {
+    let (_, (blocks, diagnostics)) = file_parser(config, &[], source_index)
+        .parse(source_index.source)
+        .unwrap();
+    assert!(diagnostics.is_empty());
+    blocks
+}
+
+fn parse_corpus(c: &mut Criterion) {
+    let config = rust_config();
+    let corpus: Vec<String> = (0..200).map(|file| generate_file(file, 25)).collect();
+    let bytes: usize = corpus.iter().map(String::len).sum();
+
+    let mut group = c.benchmark_group("parser");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.sample_size(20);
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            for source in &corpus {
+                let source_index = SourceIndex::new(Path::new("bench.rs"), source);
+                black_box(parse_file(&config, &source_index));
+            }
+        })
+    });
+    group.bench_function("parse and store", |b| {
+        b.iter(|| {
+            let mut database = AnubisDatabase::default();
+            for source in &corpus {
+                let source_index = SourceIndex::new(Path::new("bench.rs"), source);
+                database.insert_blocks(parse_file(&config, &source_index), &config);
+            }
+            black_box(database)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse_corpus);
+criterion_main!(benches);
@@ -1,9 +1,12 @@
-use crate::common::Anubis;
-use crate::config::AnubisConfig;
+use crate::common::{Anubis, AnubisError};
+use crate::config::{AnubisConfig, UnconfiguredPolicy};
 use crate::db::AnubisDatabase;
+use crate::diagnostics::report_diagnostics;
 use crate::parser::AnubisParser;
 use crate::renderer::AnubisRenderer;
 use crate::server::AnubisServer;
+use crate::tangler::AnubisTangler;
+use crate::tester::AnubisTester;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use tera::Tera;
@@ -20,6 +23,14 @@ pub struct Cli {
     #[arg(short, long, value_name = "FILE")]
     pub data: Option<PathBuf>,
 
+    /// Number of threads used to parse files, defaults to the number of CPUs
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Fail on files without a language config, whatever the config's policy is
+    #[arg(long)]
+    pub strict: bool,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -29,29 +40,85 @@ pub enum Commands {
     Parse,
     Render,
     Run,
+    Test,
+    Tangle,
     All,
 }
 
+fn parse(anubis: &mut Anubis) -> Result<(), Box<dyn std::error::Error>> {
+    let summary = anubis.parse()?;
+    report_diagnostics(&summary.diagnostics);
+    eprintln!("{summary}");
+    match summary.duplicate_errors {
+        0 => Ok(()),
+        duplicates => {
+            Err(AnubisError::ParsingError(format!("{duplicates} duplicate block(s) found")).into())
+        }
+    }
+}
+
 pub async fn process_cli() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let config = AnubisConfig::deserialize_config(cli.config.as_ref())?;
-    let database = AnubisDatabase::new(cli.data)?;
+    let mut config = AnubisConfig::deserialize_config(cli.config.as_ref())?;
+    config.jobs = cli.jobs.or(config.jobs);
+    if cli.strict {
+        config.unconfigured_files = UnconfiguredPolicy::Error;
+    }
+    // Reuse the last run's database by default, so unchanged files aren't parsed again
+    let data = cli
+        .data
+        .or_else(|| Some(PathBuf::from("./anubis.db")).filter(|path| path.exists()));
+    let database = match AnubisDatabase::new(data.clone()) {
+        Ok(database) => database,
+        // An outdated or unreadable database only costs parsing every file again
+        Err(error) if data.as_ref().is_some_and(|path| path.exists()) => {
+            eprintln!("warning: ignoring the database, every file is parsed again: {error}");
+            AnubisDatabase::default()
+        }
+        Err(error) => return Err(error),
+    };
     let tera = Tera::new("./default_templates/**/*.html")?;
 
     let mut anubis = Anubis {
         config,
         database,
         tera,
+        extensions: vec![],
     };
 
     match cli.command {
-        Some(Commands::Parse) => anubis.parse(),
-        Some(Commands::Render) => anubis.render(),
+        Some(Commands::Parse) => parse(&mut anubis),
+        Some(Commands::Render) => {
+            report_diagnostics(&anubis.render()?);
+            Ok(())
+        }
         Some(Commands::Run) => anubis.serve().await,
+        Some(Commands::Test) => {
+            let summary = anubis.test()?;
+            eprintln!("{summary}");
+            match summary.failed() {
+                0 => Ok(()),
+                failed => {
+                    Err(AnubisError::TestError(format!("{failed} doc test(s) failed")).into())
+                }
+            }
+        }
+        Some(Commands::Tangle) => {
+            let summary = anubis.tangle()?;
+            report_diagnostics(&summary.diagnostics);
+            eprintln!("{summary}");
+            match summary.errors() {
+                0 => Ok(()),
+                errors => Err(AnubisError::TangleError(format!(
+                    "tangling failed with {errors} error(s)"
+                ))
+                .into()),
+            }
+        }
         Some(Commands::All) | None => {
-            anubis.parse()?;
-            anubis.render()?;
+            parse(&mut anubis)?;
+            report_diagnostics(&anubis.render()?);
             anubis.serve().await
         }
     }
@@ -1,41 +1,261 @@
 use core::str;
 use globset::GlobSet;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
 use std::fmt;
-use std::io::Read;
-use std::{collections::HashSet, fs::File, io::BufReader, path::Path, path::PathBuf};
-use tera::Tera;
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+use tera::{Context, Tera};
 use walkdir::WalkDir;
 
 use crate::config::AnubisConfig;
-use crate::db::AnubisDatabase;
+use crate::db::{namespaced_name, AnubisDatabase};
+use crate::encoding::{read_text, DEFAULT_FALLBACK_ENCODING};
+use crate::extension::{CustomContent, Extensions};
+use crate::file_embed::FileEmbed;
+use crate::item::ItemSignature;
 
 pub struct Anubis {
     pub database: AnubisDatabase,
     pub config: AnubisConfig,
     pub tera: Tera,
+    pub extensions: Extensions,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BlockInfo {
     pub name: String,
     pub template_name: String,
+    pub args: Map<String, Value>,
+    pub parent: Option<String>,
+    pub metadata: Map<String, Value>,
+    pub location: SourceLocation,
+    #[serde(default)]
+    pub continuation: bool,
+    #[serde(default)]
+    pub fragments: Vec<BlockFragment>,
+    #[serde(default)]
+    pub signature: Option<ItemSignature>,
 }
 
+/*@[Block Fragment|Impl]
+# Block Fragment
+A block declared as `Name+` continues the block called `Name` instead of defining a new one.
+Once merged the block lists where each of its fragments was declared and how many of its
+segments came from it, starting with the block itself.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockFragment {
+    pub location: SourceLocation,
+    pub segments: usize,
+}
+/*@*/
+
+impl BlockInfo {
+    // The context shared by the block's template and its page, with the block's arguments
+    // first so the keys anubis sets take precedence over them
+    pub fn template_context(&self) -> Context {
+        let mut context = Context::new();
+        self.args
+            .iter()
+            .for_each(|(name, value)| context.insert(name, value));
+        context.insert("location", &self.location);
+        context.insert("fragments", &self.fragments);
+        context.insert("signature", &self.signature);
+        context.insert("parent", &self.parent);
+        context.insert("metadata", &self.metadata);
+        context
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.metadata
+            .get("weight")
+            .or(self.metadata.get("order"))
+            .and_then(Value::as_f64)
+            .unwrap_or(0.0)
+    }
+
+    // The name the block was declared with, blocks moved into their file's namespace keep it
+    // as a prefix of their name
+    pub fn local_name(&self) -> &str {
+        let namespace = namespaced_name(&self.location.path, "");
+        self.name.strip_prefix(&namespace).unwrap_or(&self.name)
+    }
+
+    // The name qualified by the file the block was declared in, e.g. `src/db.rs::Overview`
+    pub fn id(&self) -> String {
+        namespaced_name(&self.location.path, self.local_name())
+    }
+}
+
+/*@[Block|Impl]
+# Block
+Markdown and code borrow from the source file they were parsed from, so parsing a file only
+allocates for text that had to be rewritten, e.g. escapes or stripped comment prefixes.
+Blocks are converted to owned `Block<'static>` values when they are stored in the database.
+*/
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct Block {
+pub struct Block<'a> {
     pub info: BlockInfo,
-    pub content: Vec<BlockContent>,
+    pub content: Vec<BlockSegment<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockSegment<'a> {
+    pub content: BlockContent<'a>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum BlockContent {
-    Markdown(String),
-    Code(String),
-    Link(String),
+pub enum BlockContent<'a> {
+    Markdown(Cow<'a, str>),
+    Code(Cow<'a, str>),
+    Link(BlockLink),
     Embed(String),
+    File(FileEmbed),
+    Custom(CustomContent),
+}
+
+impl Block<'_> {
+    // Where the segment at the index was declared, which differs for continued blocks
+    pub fn fragment_location(&self, index: usize) -> &SourceLocation {
+        let mut end = 0;
+        self.info
+            .fragments
+            .iter()
+            .find(|fragment| {
+                end += fragment.segments;
+                index < end
+            })
+            .map_or(&self.info.location, |fragment| &fragment.location)
+    }
+
+    pub fn into_owned(self) -> Block<'static> {
+        Block {
+            info: self.info,
+            content: self
+                .content
+                .into_iter()
+                .map(BlockSegment::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl BlockSegment<'_> {
+    pub fn into_owned(self) -> BlockSegment<'static> {
+        BlockSegment {
+            content: self.content.into_owned(),
+            span: self.span,
+        }
+    }
+}
+
+impl BlockContent<'_> {
+    pub fn into_owned(self) -> BlockContent<'static> {
+        match self {
+            BlockContent::Markdown(markdown) => {
+                BlockContent::Markdown(markdown.into_owned().into())
+            }
+            BlockContent::Code(code) => BlockContent::Code(code.into_owned().into()),
+            BlockContent::Link(link) => BlockContent::Link(link),
+            BlockContent::Embed(embed) => BlockContent::Embed(embed),
+            BlockContent::File(file) => BlockContent::File(file),
+            BlockContent::Custom(custom) => BlockContent::Custom(custom),
+        }
+    }
+}
+/*@*/
+
+/*@[Block Link|Impl]
+# Block Link
+A link to another block, written as `Target`, `Target#Heading` or `Target#Heading|label`
+between the link delimiters.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockLink {
+    pub target: String,
+    pub heading: Option<String>,
+    pub label: Option<String>,
+}
+
+impl BlockLink {
+    pub fn parse(link: &str) -> BlockLink {
+        let (reference, label) = match link.split_once('|') {
+            Some((reference, label)) => (reference, Some(label.trim().to_string())),
+            None => (link, None),
+        };
+        let (target, heading) = match reference.split_once('#') {
+            Some((target, heading)) => (target, Some(heading.trim().to_string())),
+            None => (reference, None),
+        };
+        BlockLink {
+            target: target.trim().to_string(),
+            heading,
+            label,
+        }
+    }
+}
+/*@*/
+
+/*@[Source Location|Impl]
+# Source Location
+Byte offsets are zero based and exclusive at the end, lines and columns are one based.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    pub span: Span,
 }
 
+impl Span {
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+            start_line: self.start_line,
+            start_column: self.start_column,
+            end_line: other.end_line,
+            end_column: other.end_column,
+        }
+    }
+}
+
+impl SourceLocation {
+    pub fn with_span(&self, span: Span) -> SourceLocation {
+        SourceLocation {
+            path: self.path.clone(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.path.display(),
+            self.span.start_line,
+            self.span.start_column
+        )
+    }
+}
+/*@*/
+
 /*@[Anubis Error|join]
 {{AnubisErrorEnum}}
 {{AnubisErrorImpls}}
@@ -54,6 +274,9 @@ pub enum AnubisError {
     BlockNotFoundError(String),
     ConnectionsNotFound(String),
     ContextError(String),
+    TestError(String),
+    TangleError(String),
+    DatabaseError(String),
 }
 /*@*/
 
@@ -63,12 +286,12 @@ Traits that have been implemented for the Anubis Error Type
 */
 impl fmt::Display for AnubisError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error occured when running anubis")
+        write!(f, "{}", self.message())
     }
 }
 
-impl std::error::Error for AnubisError {
-    fn description(&self) -> &str {
+impl AnubisError {
+    pub fn message(&self) -> &str {
         match self {
             AnubisError::ParsingError(desc) => desc,
             AnubisError::ConfigError(desc) => desc,
@@ -77,9 +300,18 @@ impl std::error::Error for AnubisError {
             AnubisError::BlockNotFoundError(desc) => desc,
             AnubisError::ConnectionsNotFound(desc) => desc,
             AnubisError::ContextError(desc) => desc,
+            AnubisError::TestError(desc) => desc,
+            AnubisError::TangleError(desc) => desc,
+            AnubisError::DatabaseError(desc) => desc,
         }
     }
 }
+
+impl std::error::Error for AnubisError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
 /*@*/
 
 pub fn extract_file_extenstion(file: &Path) -> Option<&str> {
@@ -92,12 +324,23 @@ pub fn remove_ignored_files(file_list: &mut HashSet<PathBuf>, ignore_glob: GlobS
     file_list.retain(|file| !ignore_glob.is_match(file));
 }
 
+// Whether a path written in a block stays inside the project, so it's relative and its `..`
+// components never climb above the project root
+pub fn is_project_path(path: &Path) -> bool {
+    let mut depth = 0usize;
+    path.components().all(|component| match component {
+        Component::Normal(_) => {
+            depth += 1;
+            true
+        }
+        Component::CurDir => true,
+        Component::ParentDir => depth.checked_sub(1).map(|parent| depth = parent).is_some(),
+        Component::RootDir | Component::Prefix(_) => false,
+    })
+}
+
 pub fn read_file(file_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
-    let file_to_parse = File::open(file_path)?;
-    let mut file_reader = BufReader::new(file_to_parse);
-    let mut file_contents = String::new();
-    let _ = file_reader.read_to_string(&mut file_contents)?;
-    Ok(file_contents)
+    read_text(file_path, DEFAULT_FALLBACK_ENCODING)
 }
 
 pub fn collect_all_files() -> HashSet<PathBuf> {
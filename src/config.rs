@@ -1,4 +1,5 @@
 use core::str;
+use encoding_rs::Encoding;
 use globset::{Glob, GlobSet, GlobSetBuilder};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -6,20 +7,159 @@ use std::{collections::HashMap, path::Path};
 use walkdir::WalkDir;
 
 use crate::common::{extract_file_extenstion, read_file, AnubisError};
+use crate::encoding;
 
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct AnubisConfig {
     pub url: String,
     pub language_configs: HashMap<String, LanguageConfig>,
     pub anubis_ignore: Vec<String>,
+    #[serde(default)]
+    pub jobs: Option<usize>,
+    #[serde(default)]
+    pub duplicate_blocks: DuplicatePolicy,
+    #[serde(default)]
+    pub fallback_encoding: Option<String>,
+    #[serde(default)]
+    pub unconfigured_files: UnconfiguredPolicy,
+}
+
+// How blocks sharing a name with a block declared earlier in the project are reported. Blocks
+// in different files are kept under their qualified ids whatever the policy, `namespace` only
+// reports the references that can't tell them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    #[default]
+    Error,
+    Warn,
+    Namespace,
+}
+
+// How files whose extension has no language config are handled while parsing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UnconfiguredPolicy {
+    Skip,
+    #[default]
+    Warn,
+    Error,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
 pub struct LanguageConfig {
     pub language: String,
     pub anubis_character: String,
+    #[serde(default)]
     pub multiline_start: String,
+    #[serde(default)]
     pub multiline_end: String,
+    #[serde(default)]
+    pub single_line_prefixes: Vec<String>,
+    #[serde(default)]
+    pub decoration_prefixes: Vec<String>,
+    #[serde(flatten)]
+    pub delimiters: Delimiters,
+    // Run by `anubis test` on the code of blocks flagged with `test: true`
+    #[serde(default)]
+    pub test_command: Option<String>,
+    // Blocks without code of their own capture the item declared right after them
+    #[serde(default)]
+    pub attach_items: bool,
+    #[serde(default)]
+    pub doc_comments: Option<DocComments>,
+    #[serde(default)]
+    pub whole_file: Option<WholeFile>,
+    #[serde(default)]
+    pub notebook: Option<NotebookConfig>,
+}
+
+// Native doc comments imported as blocks, e.g. `///` and `//!` in rust or docstrings in python
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DocComments {
+    pub line_prefixes: Vec<String>,
+    pub module_prefixes: Vec<String>,
+    pub block_start: String,
+    pub block_end: String,
+    pub docstrings: bool,
+    pub template: String,
+}
+
+impl Default for DocComments {
+    fn default() -> Self {
+        DocComments {
+            line_prefixes: vec![],
+            module_prefixes: vec![],
+            block_start: String::new(),
+            block_end: String::new(),
+            docstrings: false,
+            template: "Impl".to_string(),
+        }
+    }
+}
+
+// Each file of the language is a single block, without any markers
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WholeFile {
+    pub template: String,
+}
+
+impl Default for WholeFile {
+    fn default() -> Self {
+        WholeFile {
+            template: "Impl".to_string(),
+        }
+    }
+}
+
+// Files of the language are read as Jupyter notebooks, each of them a block of its own unless
+// `markers_only` is set
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct NotebookConfig {
+    pub outputs: bool,
+    pub markers_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Delimiters {
+    pub link_start: String,
+    pub link_end: String,
+    pub embed_start: String,
+    pub embed_end: String,
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Delimiters {
+            link_start: "{".to_string(),
+            link_end: "}".to_string(),
+            embed_start: "{{".to_string(),
+            embed_end: "}}".to_string(),
+        }
+    }
+}
+
+impl LanguageConfig {
+    // Sequences that lose their special meaning when preceded by a backslash, longest first
+    pub fn escapable_sequences(&self) -> Vec<&str> {
+        let mut sequences: Vec<&str> = [
+            self.anubis_character.as_str(),
+            self.delimiters.link_start.as_str(),
+            self.delimiters.link_end.as_str(),
+            self.delimiters.embed_start.as_str(),
+            self.delimiters.embed_end.as_str(),
+        ]
+        .into_iter()
+        .filter(|sequence| !sequence.is_empty())
+        .collect();
+        sequences.sort_by_key(|sequence| std::cmp::Reverse(sequence.len()));
+        sequences.dedup();
+        sequences
+    }
 }
 
 impl AnubisConfig {
@@ -33,6 +173,12 @@ impl AnubisConfig {
             .ok_or(format!("Config not found for file: {:?}", file).into())
     }
 
+    // The encoding of files that are neither valid UTF-8 nor start with a byte order mark
+    pub fn fallback_encoding(&self) -> Result<&'static Encoding, AnubisError> {
+        encoding::fallback_encoding(self.fallback_encoding.as_deref())
+            .map_err(AnubisError::ConfigError)
+    }
+
     pub fn generate_ignore_glob(&self) -> Result<GlobSet, Box<dyn std::error::Error>> {
         let mut glob_builder = GlobSetBuilder::new();
         for ignore_pattern in &self.anubis_ignore {
@@ -1,33 +1,134 @@
-use crate::{common::Block, config::LanguageConfig};
+use crate::{
+    common::{AnubisError, Block, BlockContent, BlockFragment, BlockInfo},
+    config::{DuplicatePolicy, LanguageConfig},
+    diagnostics::{source_snippet, Diagnostic},
+    tester::TestResult,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     fs::File,
     io::{BufReader, BufWriter},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 use tera::Context;
 
-pub type BlockDB = HashMap<String, Block>;
+pub type BlockDB = HashMap<String, Block<'static>>;
 pub type HtmlDB = HashMap<String, String>;
 pub type GraphDB = HashMap<String, HashSet<String>>;
 pub type LangDB = HashMap<String, LanguageConfig>;
+pub type TreeDB = HashMap<String, Vec<String>>;
+pub type FileDB = HashMap<PathBuf, FileRecord>;
+pub type DuplicateDB = HashMap<String, Vec<(Block<'static>, LanguageConfig)>>;
+pub type IdDB = HashMap<String, String>;
+pub type FragmentDB = HashMap<String, Vec<Block<'static>>>;
+pub type TestDB = HashMap<String, TestResult>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BlockTree {
+    pub name: String,
+    pub id: String,
+    pub children: Vec<BlockTree>,
+}
+
+// Raised whenever the stored blocks or file records change shape, so a database written by an
+// older version is parsed again instead of being misread
+pub const DATABASE_VERSION: u32 = 1;
+
+fn current_version<S: serde::Serializer>(_: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u32(DATABASE_VERSION)
+}
 
 // Global AnubisDatabase Should only be initalised once
 #[serde_with::serde_as]
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct AnubisDatabase {
+    // The version the database was saved with, databases from before versioning have none
+    #[serde(default, serialize_with = "current_version")]
+    pub version: u32,
     pub block_db: BlockDB,
     pub html_db: HtmlDB,
     pub graph_db: GraphDB,
     pub lang_db: LangDB,
+    pub tree_db: TreeDB,
+    #[serde(default)]
+    pub file_db: FileDB,
+    #[serde(default)]
+    pub duplicate_db: DuplicateDB,
+    #[serde(default)]
+    pub id_db: IdDB,
+    #[serde(default)]
+    pub fragment_db: FragmentDB,
+    #[serde(default)]
+    pub test_db: TestDB,
+}
+
+/*@[File Record|Impl]
+# File Record
+What the last parse of a source file produced, so the file can be skipped while it is
+unchanged. A file is unchanged when the language config it was parsed with is the same and
+either its modification time or the hash of its contents still match. The hashes of the files
+its blocks embed are kept as well, so the file is parsed again when one of them changes.
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct FileRecord {
+    pub content_hash: String,
+    pub config_hash: String,
+    pub modified: Option<SystemTime>,
+    pub blocks: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<PathBuf, String>,
 }
+/*@*/
 
 impl AnubisDatabase {
-    pub fn get_block(&self, header: &str) -> Option<&Block> {
+    pub fn get_block(&self, header: &str) -> Option<&Block<'_>> {
         self.block_db.get(header)
     }
 
+    /*@[Block Ids|Impl]
+    # Block Ids
+    Besides its name every block can be addressed by an id qualified with the path of the file
+    it was declared in, e.g. `crates/db/src/lib.rs::Overview`, or with the module that file
+    defines, e.g. `db::Overview`. A reference written inside a file is first looked up in that
+    file's namespace, and then in the global one.
+    */
+    pub fn resolve(&self, source: &Path, reference: &str) -> Option<&str> {
+        self.resolve_id(&namespaced_name(source, reference))
+            .or_else(|| self.resolve_id(reference))
+    }
+
+    pub fn resolve_id(&self, reference: &str) -> Option<&str> {
+        self.id_db.get(reference).map(String::as_str)
+    }
+
+    fn block_ids(info: &BlockInfo) -> Vec<String> {
+        let mut ids = vec![info.name.clone(), info.id()];
+        if let Some(module) = module_name(&info.location.path) {
+            ids.push(format!("{module}::{}", info.local_name()));
+        }
+        ids
+    }
+
+    // The first block to claim a module id keeps it, the other ids are unique
+    fn insert_ids(&mut self, info: &BlockInfo) {
+        for id in Self::block_ids(info) {
+            self.id_db.entry(id).or_insert_with(|| info.name.clone());
+        }
+    }
+
+    fn remove_ids(&mut self, info: &BlockInfo) {
+        for id in Self::block_ids(info) {
+            if self.id_db.get(&id) == Some(&info.name) {
+                self.id_db.remove(&id);
+            }
+        }
+    }
+    /*@*/
+
     pub fn get_html(&self, header: &str) -> Option<&String> {
         self.html_db.get(header)
     }
@@ -40,31 +141,417 @@ impl AnubisDatabase {
         self.lang_db.get(header)
     }
 
+    pub fn get_children(&self, header: &str) -> Vec<&BlockInfo> {
+        let mut children: Vec<&BlockInfo> = self
+            .tree_db
+            .get(header)
+            .into_iter()
+            .flatten()
+            .filter_map(|child| self.get_block(child))
+            .map(|child| &child.info)
+            .collect();
+        children.sort_by(|a, b| a.weight().total_cmp(&b.weight()));
+        children
+    }
+
+    // Blocks whose metadata key equals the value, or holds a list containing it
+    pub fn query_metadata(&self, key: &str, value: &Value) -> Vec<&BlockInfo> {
+        let mut matches: Vec<&BlockInfo> = self
+            .block_db
+            .values()
+            .map(|block| &block.info)
+            .filter(|info| match info.metadata.get(key) {
+                Some(Value::Array(values)) => values.contains(value),
+                Some(metadata_value) => metadata_value == value,
+                None => false,
+            })
+            .collect();
+        matches.sort_by(|a, b| a.weight().total_cmp(&b.weight()).then(a.name.cmp(&b.name)));
+        matches
+    }
+
+    pub fn get_tags(&self) -> BTreeMap<String, Vec<String>> {
+        let mut tags: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for info in self.block_db.values().map(|block| &block.info) {
+            let block_tags = match info.metadata.get("tags") {
+                Some(Value::Array(block_tags)) => block_tags.iter().collect(),
+                Some(block_tag) => vec![block_tag],
+                None => vec![],
+            };
+            block_tags
+                .into_iter()
+                .filter_map(Value::as_str)
+                .for_each(|tag| {
+                    tags.entry(tag.to_string())
+                        .or_default()
+                        .push(info.name.clone())
+                });
+        }
+        tags.values_mut().for_each(|names| names.sort());
+        tags
+    }
+
+    pub fn get_block_tree(&self) -> Vec<BlockTree> {
+        let mut roots: Vec<&BlockInfo> = self
+            .block_db
+            .values()
+            .map(|block| &block.info)
+            .filter(|info| info.parent.is_none())
+            .collect();
+        roots.sort_by(|a, b| a.weight().total_cmp(&b.weight()).then(a.name.cmp(&b.name)));
+        let mut visited = HashSet::new();
+        roots
+            .into_iter()
+            .filter_map(|root| self.build_tree(&root.name, &mut visited))
+            .collect()
+    }
+
+    fn build_tree(&self, header: &str, visited: &mut HashSet<String>) -> Option<BlockTree> {
+        if !visited.insert(header.to_string()) {
+            return None;
+        }
+        let id = self.get_block(header)?.info.id();
+        let children = self
+            .get_children(header)
+            .into_iter()
+            .filter_map(|child| self.build_tree(&child.name, visited))
+            .collect();
+        Some(BlockTree {
+            name: header.to_string(),
+            id,
+            children,
+        })
+    }
+
     pub fn get_context(&self, header: &str) -> Option<Context> {
-        let mut context = Context::new();
+        let block = self.get_block(header)?;
+        let neighbors = self.get_connections(header)?;
+        let ids: HashMap<&String, String> = neighbors
+            .iter()
+            .map(|neighbor| {
+                let id = self
+                    .get_block(neighbor)
+                    .map_or(neighbor.clone(), |block| block.info.id());
+                (neighbor, id)
+            })
+            .collect();
+        let mut context = block.info.template_context();
         context.insert("html", self.get_html(header)?);
-        context.insert("neighbors", self.get_connections(header)?);
+        context.insert("id", &block.info.id());
+        context.insert("neighbors", neighbors);
+        context.insert("ids", &ids);
+        context.insert("test", &self.test_db.get(header));
+        context.insert("children", &self.get_children(header));
+        context.insert("tree", &self.get_block_tree());
         Some(context)
     }
 
-    pub fn insert_block(&mut self, block: &Block, lang: &LanguageConfig) {
-        let connections = block.content.iter().filter_map(|content| match content {
-            crate::common::BlockContent::Embed(header) => Some(header),
-            crate::common::BlockContent::Link(header) => Some(header),
-            _ => None,
+    // A block whose name is already taken is held back until the duplicates are resolved, and
+    // continuations are held back until they are merged into the block they continue
+    pub fn insert_block(&mut self, block: Block, lang: &LanguageConfig) {
+        let name = block.info.name.clone();
+        if block.info.continuation {
+            self.fragment_db
+                .entry(name)
+                .or_default()
+                .push(block.into_owned());
+            return;
+        }
+        if let Some(duplicates) = self.duplicate_db.get_mut(&name) {
+            duplicates.push((block.into_owned(), lang.clone()));
+            return;
+        }
+        if let Some(existing) = self.block_db.remove(&name) {
+            self.remove_ids(&existing.info);
+            let existing_lang = self.lang_db.remove(&name).unwrap_or_default();
+            self.html_db.remove(&name);
+            self.duplicate_db.insert(
+                name,
+                vec![
+                    (existing, existing_lang),
+                    (block.into_owned(), lang.clone()),
+                ],
+            );
+            return;
+        }
+        self.insert_ids(&block.info);
+        self.link_block(&block);
+        self.lang_db.insert(block.info.name.clone(), lang.clone());
+        self.block_db
+            .insert(block.info.name.clone(), block.into_owned());
+    }
+
+    fn link_block(&mut self, block: &Block) {
+        let connections: Vec<String> = block
+            .content
+            .iter()
+            .enumerate()
+            .filter_map(|(index, segment)| match &segment.content {
+                BlockContent::Embed(header) => Some((index, header)),
+                BlockContent::Link(link) => Some((index, &link.target)),
+                _ => None,
+            })
+            .map(|(index, reference)| {
+                let source = &block.fragment_location(index).path;
+                self.resolve(source, reference)
+                    .unwrap_or(reference)
+                    .to_string()
+            })
+            .collect();
+
+        self.graph_db.entry(block.info.name.clone()).or_default();
+        connections.into_iter().for_each(|connection| {
+            self.add_edge_undirected(block.info.name.clone(), connection);
         });
 
-        connections.for_each(|connection| {
-            self.add_edge_undirected(block.info.name.clone(), connection.to_string());
+        if let Some(parent) = &block.info.parent {
+            let source = &block.info.location.path;
+            let parent = self.resolve(source, parent).unwrap_or(parent).to_string();
+            let siblings = self.tree_db.entry(parent).or_default();
+            if !siblings.contains(&block.info.name) {
+                siblings.push(block.info.name.clone());
+            }
+        }
+    }
+
+    pub fn remove_block(&mut self, header: &str) {
+        if let Some(block) = self.block_db.remove(header) {
+            self.remove_ids(&block.info);
+        }
+        self.html_db.remove(header);
+        self.lang_db.remove(header);
+        self.test_db.remove(header);
+    }
+
+    // Links and children are derived from the stored blocks, in the order they were declared
+    pub fn rebuild_relations(&mut self) {
+        self.graph_db.clear();
+        self.tree_db.clear();
+        self.id_db.clear();
+        let block_db = std::mem::take(&mut self.block_db);
+        let mut blocks: Vec<&Block> = block_db.values().collect();
+        blocks.sort_by_key(|block| (&block.info.location.path, block.info.location.span.start));
+        blocks.iter().for_each(|block| self.insert_ids(&block.info));
+        blocks.into_iter().for_each(|block| self.link_block(block));
+        self.block_db = block_db;
+    }
+
+    pub fn get_file(&self, path: &Path) -> Option<&FileRecord> {
+        self.file_db.get(path)
+    }
+
+    pub fn insert_file(&mut self, path: PathBuf, record: FileRecord) {
+        self.file_db.insert(path, record);
+    }
+
+    // Removes a file's record along with the blocks it defined
+    pub fn remove_file(&mut self, path: &Path) -> Option<FileRecord> {
+        let record = self.file_db.remove(path)?;
+        for header in &record.blocks {
+            if self
+                .get_block(header)
+                .is_some_and(|block| block.info.location.path == path)
+            {
+                self.remove_block(header);
+            }
+            if let Some(duplicates) = self.duplicate_db.get_mut(header) {
+                duplicates.retain(|(block, _)| block.info.location.path != path);
+                self.remove_block(&namespaced_name(path, header));
+            }
+            if let Some(fragments) = self.fragment_db.get_mut(header) {
+                fragments.retain(|fragment| fragment.info.location.path != path);
+            }
+        }
+        Some(record)
+    }
+
+    /*@[Duplicate Blocks|Impl]
+    # Duplicate Blocks
+    Every declaration of a name used more than once is kept in the duplicate db, so the
+    duplicates can be resolved again whenever any of the files declaring them changes. The
+    declaration that comes first by path and position keeps the name, and the first one in
+    each other file is kept as well, renamed to its qualified id `path::Name`. A name declared
+    twice in the same file is always reported, and only its first declaration in that file is
+    kept. With the `error` and `warn` policies a name declared in several files is reported as
+    well, with the location of the declaration keeping the name. The `namespace` policy
+    accepts names repeated across files, and instead reports the references to them written
+    outside the files declaring them, since those can only guess which block they meant.
+    */
+    pub fn resolve_duplicates(&mut self, policy: DuplicatePolicy) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        let mut names: Vec<String> = self.duplicate_db.keys().cloned().collect();
+        names.sort();
+        for name in names {
+            let mut duplicates = self.duplicate_db.remove(&name).unwrap_or_default();
+            duplicates.sort_by(|(a, _), (b, _)| {
+                (&a.info.location.path, a.info.location.span.start)
+                    .cmp(&(&b.info.location.path, b.info.location.span.start))
+            });
+            let Some((first, first_lang)) = duplicates.first() else {
+                continue;
+            };
+            self.remove_block(&namespaced_name(&first.info.location.path, &name));
+            self.insert_ids(&first.info);
+            self.lang_db.insert(name.clone(), first_lang.clone());
+            self.block_db.insert(name.clone(), first.clone());
+
+            // The declarations are sorted by path, so each file's first one is met before the rest
+            let mut kept = first;
+            for (duplicate, lang) in &duplicates[1..] {
+                let location = &duplicate.info.location;
+                let namespaced = namespaced_name(&location.path, &name);
+                let message = if location.path != kept.info.location.path {
+                    kept = duplicate;
+                    let mut block = duplicate.clone();
+                    block.info.name = namespaced.clone();
+                    self.insert_ids(&block.info);
+                    self.lang_db.insert(namespaced.clone(), lang.clone());
+                    self.block_db.insert(namespaced.clone(), block);
+                    if policy == DuplicatePolicy::Namespace {
+                        continue;
+                    }
+                    format!(
+                        "duplicate block `{name}`, first defined at {}, kept as `{namespaced}`",
+                        first.info.location
+                    )
+                } else {
+                    format!(
+                        "duplicate block `{name}`, first defined at {}",
+                        kept.info.location
+                    )
+                };
+                let snippet = source_snippet(location);
+                diagnostics.push(match policy {
+                    DuplicatePolicy::Error => Diagnostic::error(message, location.clone(), snippet),
+                    DuplicatePolicy::Warn | DuplicatePolicy::Namespace => {
+                        Diagnostic::warning(message, location.clone(), snippet)
+                    }
+                });
+            }
+
+            if duplicates.len() > 1 {
+                self.duplicate_db.insert(name, duplicates);
+            }
+        }
+        if policy == DuplicatePolicy::Namespace {
+            diagnostics.extend(self.ambiguous_references());
+        }
+        diagnostics
+    }
+
+    // References to a name declared in several files, written in a file declaring none of them
+    fn ambiguous_references(&self) -> Vec<Diagnostic> {
+        let mut declarations: HashMap<&str, Vec<String>> = HashMap::new();
+        for (name, duplicates) in &self.duplicate_db {
+            let mut paths: Vec<&Path> = vec![];
+            for (duplicate, _) in duplicates {
+                let location = &duplicate.info.location;
+                if !paths.contains(&location.path.as_path()) {
+                    paths.push(&location.path);
+                    declarations
+                        .entry(name)
+                        .or_default()
+                        .push(location.to_string());
+                }
+            }
+        }
+        declarations.retain(|_, locations| locations.len() > 1);
+
+        let mut diagnostics = vec![];
+        for block in self.block_db.values() {
+            for (index, segment) in block.content.iter().enumerate() {
+                let reference = match &segment.content {
+                    BlockContent::Embed(reference) => reference,
+                    BlockContent::Link(link) => &link.target,
+                    _ => continue,
+                };
+                let location = block.fragment_location(index);
+                let Some(locations) = declarations.get(reference.as_str()) else {
+                    continue;
+                };
+                if self
+                    .resolve_id(&namespaced_name(&location.path, reference))
+                    .is_some()
+                {
+                    continue;
+                }
+                let location = location.clone().with_span(segment.span);
+                let message =
+                    format!(
+                    "`{reference}` is declared in several files ({}), it refers to the first one, \
+                     use a qualified id such as `{}::{reference}` instead",
+                    locations.join(", "),
+                    self.block_db[reference.as_str()].info.location.path.display()
+                );
+                let snippet = source_snippet(&location);
+                diagnostics.push(Diagnostic::warning(message, location, snippet));
+            }
+        }
+        diagnostics
+    }
+    /*@*/
+
+    /*@[Block Continuations|Impl]
+    # Block Continuations
+    Continuations are kept in the fragment db and merged into a copy of the block they continue,
+    so they can be merged again whenever any of their files changes. A continuation looks for
+    the block in its own file's namespace first. The block's own content comes first, followed
+    by its continuations sorted by weight, then by path and position.
+    */
+    pub fn merge_fragments(&mut self) -> Vec<Diagnostic> {
+        for block in self.block_db.values_mut() {
+            if let Some(own) = block.info.fragments.first() {
+                block.content.truncate(own.segments);
+                block.info.fragments.clear();
+            }
+        }
+        self.fragment_db
+            .retain(|_, fragments| !fragments.is_empty());
+
+        let mut diagnostics = vec![];
+        let mut fragments: Vec<&Block> = self.fragment_db.values().flatten().collect();
+        fragments.sort_by(|a, b| {
+            a.info.weight().total_cmp(&b.info.weight()).then(
+                (&a.info.location.path, a.info.location.span.start)
+                    .cmp(&(&b.info.location.path, b.info.location.span.start)),
+            )
         });
+        let mut merges: Vec<(String, Block<'static>)> = vec![];
+        for fragment in fragments {
+            let location = &fragment.info.location;
+            match self.resolve(&location.path, &fragment.info.name) {
+                Some(header) => merges.push((header.to_string(), fragment.clone())),
+                None => diagnostics.push(Diagnostic::error(
+                    format!("continuation of unknown block `{}`", fragment.info.name),
+                    location.clone(),
+                    source_snippet(location),
+                )),
+            }
+        }
 
-        self.lang_db.insert(block.info.name.clone(), lang.clone());
-        self.block_db.insert(block.info.name.clone(), block.clone());
+        for (header, fragment) in merges {
+            let Some(block) = self.block_db.get_mut(&header) else {
+                continue;
+            };
+            if block.info.fragments.is_empty() {
+                block.info.fragments.push(BlockFragment {
+                    location: block.info.location.clone(),
+                    segments: block.content.len(),
+                });
+            }
+            block.info.fragments.push(BlockFragment {
+                location: fragment.info.location,
+                segments: fragment.content.len(),
+            });
+            block.content.extend(fragment.content);
+        }
+        diagnostics
     }
+    /*@*/
 
     pub fn insert_blocks(&mut self, blocks: Vec<Block>, lang: &LanguageConfig) {
         blocks
-            .iter()
+            .into_iter()
             .for_each(|block| self.insert_block(block, lang));
     }
 
@@ -95,10 +582,34 @@ impl AnubisDatabase {
 
     pub fn new(db_path: Option<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
         if let Some(path) = db_path {
-            let file = File::open(path)?;
+            let file = File::open(&path)?;
             let reader = BufReader::new(file);
-            return Ok(serde_json::from_reader(reader)?);
+            let database: AnubisDatabase = serde_json::from_reader(reader)?;
+            if database.version != DATABASE_VERSION {
+                return Err(AnubisError::DatabaseError(format!(
+                    "{} was saved by another version of anubis",
+                    path.display()
+                ))
+                .into());
+            }
+            return Ok(database);
         }
         Ok(AnubisDatabase::default())
     }
 }
+
+pub fn namespaced_name(path: &Path, name: &str) -> String {
+    format!("{}::{name}", path.display())
+}
+
+// The module a file defines, e.g. `db` for both `src/db.rs` and `crates/db/src/lib.rs`
+pub fn module_name(path: &Path) -> Option<&str> {
+    let stem = path.file_stem()?.to_str()?;
+    if !["mod", "lib", "main", "index", "__init__"].contains(&stem) {
+        return Some(stem);
+    }
+    path.parent()?
+        .ancestors()
+        .filter_map(|directory| directory.file_name()?.to_str())
+        .find(|directory| *directory != "src")
+}
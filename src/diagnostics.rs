@@ -0,0 +1,100 @@
+use crate::common::{read_file, SourceLocation};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/*@[Diagnostic|Impl]
+# Diagnostic
+A problem found while processing the project, along with where it was found.
+The offending source line is kept so the message can be printed without
+re-reading the file.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: SourceLocation,
+    pub snippet: String,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, location: SourceLocation, snippet: String) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            location,
+            snippet,
+        }
+    }
+
+    pub fn warning(message: String, location: SourceLocation, snippet: String) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            message,
+            location,
+            snippet,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line_number = self.location.span.start_line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let caret_offset = " ".repeat(self.location.span.start_column.saturating_sub(1));
+        writeln!(f, "{}: {}", self.severity, self.message)?;
+        writeln!(f, "{gutter}--> {}", self.location)?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line_number} | {}", self.snippet)?;
+        write!(f, "{gutter} | {caret_offset}^")
+    }
+}
+/*@*/
+
+// Reads the source line a location points at, for diagnostics raised after parsing
+pub fn source_snippet(location: &SourceLocation) -> String {
+    read_file(&location.path)
+        .ok()
+        .and_then(|contents| {
+            contents
+                .lines()
+                .nth(location.span.start_line.saturating_sub(1))
+                .map(str::to_string)
+        })
+        .unwrap_or_default()
+}
+
+pub fn sort_diagnostics(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by(|a, b| {
+        (&a.location.path, a.location.span.start).cmp(&(&b.location.path, b.location.span.start))
+    });
+}
+
+pub fn report_diagnostics(diagnostics: &[Diagnostic]) {
+    diagnostics
+        .iter()
+        .for_each(|diagnostic| eprintln!("{diagnostic}\n"));
+
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    if !diagnostics.is_empty() {
+        eprintln!("{errors} error(s), {warnings} warning(s) emitted");
+    }
+}
@@ -0,0 +1,351 @@
+use crate::common::{Block, BlockContent, BlockInfo, BlockSegment};
+use crate::config::{DocComments, LanguageConfig};
+use crate::db::{module_name, namespaced_name};
+use crate::item::{find_item, is_attribute, Item};
+use crate::parser_core::SourceIndex;
+use nom::Offset;
+use serde_json::Map;
+use std::borrow::Cow;
+
+/*@[Doc Comment Import|Impl]
+# Doc Comment Import
+With `doc_comments` set for a language, the doc comments already written in its native
+style become blocks too, e.g. `///` or `/** */` comments and python docstrings. Each one is
+named after the item it documents, with items declared inside an `impl`, `class` or similar
+named after it as well, e.g. `Shape::area` or `<Shape as Display>::fmt` for a trait impl,
+and made a child of its block when that is documented too. Comments documenting the file
+itself, such as `//!`, are named after the module. Names of items such as `new` or `main`
+repeat across files, so the names are qualified with the file's namespace, e.g.
+`src/shape.rs::Shape::area`, and other files link to them by path or module, e.g.
+`\{shape::Shape::area}`. The comment is kept as plain markdown followed by the item's code,
+and the block uses the template from the config, `Impl` unless set.
+*/
+const CONTAINER_KEYWORDS: [&str; 5] = ["impl", "trait", "class", "interface", "mod"];
+const DOCSTRING_QUOTES: [&str; 2] = ["\"\"\"", "'''"];
+
+struct DocComment<'a> {
+    text: String,
+    source: &'a str,
+    module: bool,
+}
+
+// An item the following items are declared in, until its end
+struct Container {
+    name: String,
+    block: Option<String>,
+    start: usize,
+    end: usize,
+}
+
+struct Importer<'a, 's> {
+    doc_comments: &'s DocComments,
+    source_index: &'s SourceIndex<'a>,
+    containers: Vec<Container>,
+    blocks: Vec<Block<'a>>,
+}
+
+pub fn import_doc_comments<'a>(
+    language_config: &LanguageConfig,
+    doc_comments: &DocComments,
+    source_index: &SourceIndex<'a>,
+) -> Vec<Block<'a>> {
+    let source = source_index.source;
+    let mut importer = Importer {
+        doc_comments,
+        source_index,
+        containers: vec![],
+        blocks: vec![],
+    };
+    if doc_comments.docstrings {
+        if let Some(docstring) = module_docstring(source) {
+            importer.add_module(docstring);
+        }
+    }
+
+    let mut offset = 0;
+    while offset < source.len() {
+        importer
+            .containers
+            .retain(|container| container.end > offset);
+        let rest = &source[offset..];
+        if let Some(comment) = doc_comment(rest, doc_comments) {
+            offset += comment.source.len();
+            if comment.module {
+                importer.add_module(comment);
+            } else if let Some(item) = find_item(&source[offset..], language_config) {
+                importer.add_item(item, Some(comment));
+            }
+            continue;
+        }
+
+        let line = rest.split_inclusive('\n').next().unwrap_or(rest);
+        let mut next = offset + line.len();
+        let attribute = is_attribute(line.trim_start());
+        if attribute || importer.may_start_item(line) {
+            if let Some(item) = find_item(rest, language_config) {
+                // The attributes or decorators belong to the item, so scanning goes on after
+                // the line with its keyword
+                if attribute {
+                    let keyword_line =
+                        offset + rest.find(item.signature.text.as_str()).unwrap_or(0);
+                    next = source[keyword_line..]
+                        .find('\n')
+                        .map_or(source.len(), |end| keyword_line + end + 1);
+                }
+                let docstring = docstring(&item).filter(|_| doc_comments.docstrings);
+                importer.add_item(item, docstring);
+            }
+        }
+        offset = next;
+    }
+    importer.blocks
+}
+
+impl<'a> Importer<'a, '_> {
+    // Only lines that could declare a container or a documented `def` are looked at closely
+    fn may_start_item(&self, line: &str) -> bool {
+        line.split_whitespace().any(|word| {
+            let word = word
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .next()
+                .unwrap_or_default();
+            CONTAINER_KEYWORDS.contains(&word) || (self.doc_comments.docstrings && word == "def")
+        })
+    }
+
+    fn add_module(&mut self, comment: DocComment<'a>) {
+        let path = &self.source_index.path;
+        let Some(name) = module_name(path).or(path.file_stem().and_then(|stem| stem.to_str()))
+        else {
+            return;
+        };
+        let block = self.block(name.to_string(), comment, None, None);
+        self.blocks.push(block);
+    }
+
+    fn add_item(&mut self, item: Item<'a>, comment: Option<DocComment<'a>>) {
+        let start = self.source_index.source.offset(item.code);
+        // A documented container is found again when its own line is reached
+        if self
+            .containers
+            .iter()
+            .any(|container| container.start == start)
+        {
+            return;
+        }
+        let container = self.containers.last();
+        let local_name = match item.signature.kind.as_str() {
+            "impl" => impl_name(&item.signature.name),
+            _ => item.signature.name.clone(),
+        };
+        let name = match container {
+            Some(container) => format!("{}::{local_name}", container.name),
+            None => local_name,
+        };
+        let parent = container.and_then(|container| container.block.clone());
+        let documented = comment.is_some();
+        if CONTAINER_KEYWORDS.contains(&item.signature.kind.as_str()) {
+            self.containers.push(Container {
+                name: name.clone(),
+                block: documented.then(|| name.clone()),
+                start,
+                end: start + item.code.len(),
+            });
+        }
+        if let Some(comment) = comment {
+            let block = self.block(name, comment, Some(item), parent);
+            self.blocks.push(block);
+        }
+    }
+
+    fn block(
+        &self,
+        name: String,
+        comment: DocComment<'a>,
+        item: Option<Item<'a>>,
+        parent: Option<String>,
+    ) -> Block<'a> {
+        let mut content = vec![BlockSegment {
+            content: BlockContent::Markdown(Cow::Owned(comment.text)),
+            span: self.source_index.span(comment.source),
+        }];
+        let signature = item.map(|item| {
+            content.push(BlockSegment {
+                content: BlockContent::Code(Cow::Borrowed(item.code)),
+                span: self.source_index.span(item.code),
+            });
+            item.signature
+        });
+        let path = &self.source_index.path;
+        Block {
+            info: BlockInfo {
+                name: namespaced_name(path, &name),
+                template_name: self.doc_comments.template.clone(),
+                args: Map::new(),
+                parent: parent.map(|parent| namespaced_name(path, &parent)),
+                metadata: Map::new(),
+                location: self.source_index.location(comment.source),
+                continuation: false,
+                fragments: vec![],
+                signature,
+            },
+            content,
+        }
+    }
+}
+
+// `impl Shape<T>` declares items of `Shape`, and `impl<T> Display for Shape<T>` items of
+// `<Shape as Display>`, which keeps them apart from the items of other impls for `Shape`
+fn impl_name(signature_name: &str) -> String {
+    let without_generics = |name: &str| {
+        name.split('<')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    match signature_name.rsplit_once(" for ") {
+        Some((trait_name, type_name)) => format!(
+            "<{} as {}>",
+            without_generics(type_name),
+            without_generics(trait_name)
+        ),
+        None => without_generics(signature_name),
+    }
+}
+
+// A run of line doc comments, or a block doc comment, starting on the first line of the text
+fn doc_comment<'a>(text: &'a str, doc_comments: &DocComments) -> Option<DocComment<'a>> {
+    let first_line = text.split_inclusive('\n').next()?.trim_start();
+    let line_prefix = |prefixes: &[String]| {
+        prefixes
+            .iter()
+            .find(|prefix| doc_line(first_line, prefix).is_some())
+            .cloned()
+    };
+    if let Some(prefix) = line_prefix(&doc_comments.line_prefixes) {
+        return Some(line_comment(text, &prefix, false));
+    }
+    if let Some(prefix) = line_prefix(&doc_comments.module_prefixes) {
+        return Some(line_comment(text, &prefix, true));
+    }
+    let (start, end) = (&doc_comments.block_start, &doc_comments.block_end);
+    let body = first_line.strip_prefix(start.as_str())?;
+    if start.is_empty() || end.is_empty() || body.starts_with(['*', '/']) {
+        return None;
+    }
+    let body_start = text.offset(body);
+    let body_end = body_start + text[body_start..].find(end.as_str())?;
+    let comment_end = body_end + end.len();
+    let comment_end = match text[comment_end..].find('\n') {
+        Some(newline) if text[comment_end..comment_end + newline].trim().is_empty() => {
+            comment_end + newline + 1
+        }
+        _ => comment_end,
+    };
+    let lines: Vec<&str> = text[body_start..body_end]
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.strip_prefix(' ').unwrap_or(line)
+        })
+        .collect();
+    Some(DocComment {
+        text: trim_blank_lines(&lines),
+        source: &text[..comment_end],
+        module: false,
+    })
+}
+
+// The text of a doc comment line, which `////` doesn't count as for a `///` prefix
+fn doc_line<'l>(line: &'l str, prefix: &str) -> Option<&'l str> {
+    let text = line.trim_start().strip_prefix(prefix)?;
+    match prefix.chars().last() {
+        Some(last) if text.starts_with(last) => None,
+        _ => Some(
+            text.strip_prefix(' ')
+                .unwrap_or(text)
+                .trim_end_matches(['\n', '\r']),
+        ),
+    }
+}
+
+fn line_comment<'a>(text: &'a str, prefix: &str, module: bool) -> DocComment<'a> {
+    let mut lines = vec![];
+    let mut length = 0;
+    for line in text.split_inclusive('\n') {
+        let Some(doc) = doc_line(line, prefix) else {
+            break;
+        };
+        lines.push(doc);
+        length += line.len();
+    }
+    DocComment {
+        text: trim_blank_lines(&lines),
+        source: &text[..length],
+        module,
+    }
+}
+
+fn trim_blank_lines(lines: &[&str]) -> String {
+    let start = lines.iter().position(|line| !line.trim().is_empty());
+    let end = lines.iter().rposition(|line| !line.trim().is_empty());
+    match (start, end) {
+        (Some(start), Some(end)) => lines[start..=end].join("\n") + "\n",
+        _ => String::new(),
+    }
+}
+
+// The docstring opening the body of a `def` or `class`
+fn docstring<'a>(item: &Item<'a>) -> Option<DocComment<'a>> {
+    let header_end = item.code.find(item.signature.text.as_str())? + item.signature.text.len();
+    let body = item.code[header_end..].trim_start().strip_prefix(':')?;
+    string_literal(body.trim_start())
+}
+
+fn module_docstring(source: &str) -> Option<DocComment<'_>> {
+    let start = source
+        .split_inclusive('\n')
+        .take_while(|line| line.trim().is_empty() || line.trim_start().starts_with('#'))
+        .map(str::len)
+        .sum::<usize>();
+    let mut docstring = string_literal(source[start..].trim_start())?;
+    docstring.module = true;
+    Some(docstring)
+}
+
+fn string_literal(text: &str) -> Option<DocComment<'_>> {
+    let unprefixed = text.strip_prefix(['r', 'R']).unwrap_or(text);
+    let quote = DOCSTRING_QUOTES
+        .into_iter()
+        .find(|quote| unprefixed.starts_with(quote))?;
+    let body_start = text.offset(unprefixed) + quote.len();
+    let body_end = body_start + text[body_start..].find(quote)?;
+    Some(DocComment {
+        text: clean_docstring(&text[body_start..body_end]),
+        source: &text[..body_end + quote.len()],
+        module: false,
+    })
+}
+
+// Like python's `inspect.cleandoc`, the lines after the first are dedented
+fn clean_docstring(docstring: &str) -> String {
+    let mut lines = docstring.lines();
+    let first = lines.next().unwrap_or_default().trim();
+    let rest: Vec<&str> = lines.collect();
+    let indentation = rest
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = std::iter::once(first)
+        .chain(
+            rest.iter()
+                .map(|line| line.get(indentation..).unwrap_or(line.trim_start())),
+        )
+        .collect();
+    trim_blank_lines(&lines)
+}
+/*@*/
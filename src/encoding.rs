@@ -0,0 +1,73 @@
+use encoding_rs::{Encoding, WINDOWS_1252};
+use std::{borrow::Cow, fs, path::Path};
+
+/*@[Text Decoding|Impl]
+# Text Decoding
+Files are read as bytes and decoded before they are parsed. A byte order mark selects UTF-8
+or UTF-16, files without one are read as UTF-8, and files that aren't valid UTF-8 are decoded
+with the `fallback_encoding` from the config, `windows-1252` unless set. A file with a NUL
+byte near its start is treated as binary and skipped. Line endings are normalized to `\n`,
+so a CRLF file parses the same as its LF version.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceText {
+    Text(String),
+    Binary,
+}
+
+pub const DEFAULT_FALLBACK_ENCODING: &Encoding = WINDOWS_1252;
+
+// How many bytes are searched for a NUL before a file is accepted as text
+const BINARY_SNIFF_LENGTH: usize = 8000;
+
+pub fn fallback_encoding(label: Option<&str>) -> Result<&'static Encoding, String> {
+    match label {
+        None => Ok(DEFAULT_FALLBACK_ENCODING),
+        Some(label) => Encoding::for_label(label.trim().as_bytes())
+            .ok_or(format!("unknown encoding `{label}`")),
+    }
+}
+
+pub fn decode(bytes: &[u8], fallback: &'static Encoding) -> SourceText {
+    let text = match Encoding::for_bom(bytes) {
+        Some((encoding, bom_length)) => {
+            encoding.decode_without_bom_handling(&bytes[bom_length..]).0
+        }
+        None if is_binary(bytes) => return SourceText::Binary,
+        None => match std::str::from_utf8(bytes) {
+            Ok(text) => Cow::Borrowed(text),
+            Err(_) => fallback.decode_without_bom_handling(bytes).0,
+        },
+    };
+    SourceText::Text(normalize_newlines(&text))
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_LENGTH)].contains(&0)
+}
+
+pub fn normalize_newlines(text: &str) -> String {
+    if text.contains('\r') {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn read_source(
+    file_path: &Path,
+    fallback: &'static Encoding,
+) -> Result<SourceText, std::io::Error> {
+    Ok(decode(&fs::read(file_path)?, fallback))
+}
+
+pub fn read_text(
+    file_path: &Path,
+    fallback: &'static Encoding,
+) -> Result<String, Box<dyn std::error::Error>> {
+    match read_source(file_path, fallback)? {
+        SourceText::Text(text) => Ok(text),
+        SourceText::Binary => Err("binary file".into()),
+    }
+}
+/*@*/
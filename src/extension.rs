@@ -0,0 +1,49 @@
+use crate::common::Anubis;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/*@[Content Extensions|Impl]
+# Content Extensions
+Library users can add their own inline constructs, such as `\{!issue 123}` or `\{=term}`, by
+registering a content extension on `Anubis`. Text between the extension's start and end
+sequences is offered to the extensions before links and embeds, and the data the first one
+to accept it returns is stored in the database under the extension's name. When rendering,
+the content is handed back to the extension with the same name.
+*/
+pub trait ContentExtension: Send + Sync {
+    // Stored with the parsed content, so it should stay the same between runs
+    fn name(&self) -> &str;
+
+    fn start(&self) -> &str;
+
+    fn end(&self) -> &str {
+        "}"
+    }
+
+    // The text between the start and end sequences, or None to leave it to other parsers
+    fn parse(&self, text: &str) -> Option<Value>;
+
+    fn render(&self, data: &Value, anubis: &Anubis) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomContent {
+    pub extension: String,
+    pub data: Value,
+}
+
+pub type Extensions = Vec<Box<dyn ContentExtension>>;
+
+impl Anubis {
+    pub fn register_extension(&mut self, extension: impl ContentExtension + 'static) {
+        self.extensions.push(Box::new(extension));
+    }
+
+    pub fn get_extension(&self, name: &str) -> Option<&dyn ContentExtension> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.name() == name)
+            .map(|extension| extension.as_ref())
+    }
+}
+/*@*/
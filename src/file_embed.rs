@@ -0,0 +1,123 @@
+use crate::common::is_project_path;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/*@[File Embed|Impl]
+# File Embed
+An embed starting with `file:` pulls code from any file in the repository instead of
+another block. The path is relative to the project root, which it can't leave through `..` or
+an absolute path, and may be followed by a line range, `#L17-L40` or `#L17`, or by a region,
+`#region=save`. A region is the code between a line
+containing `#region save` and its matching `#endregion` line, which are left out.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEmbed {
+    pub path: PathBuf,
+    pub selection: FileSelection,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileSelection {
+    Whole,
+    Lines { start: usize, end: usize },
+    Region(String),
+}
+
+impl FileEmbed {
+    pub fn parse(reference: &str) -> Result<FileEmbed, String> {
+        let (path, fragment) = match reference.split_once('#') {
+            Some((path, fragment)) => (path.trim(), Some(fragment.trim())),
+            None => (reference.trim(), None),
+        };
+        if path.is_empty() {
+            return Err("missing file path".to_string());
+        }
+        if !is_project_path(Path::new(path)) {
+            return Err(format!("`{path}` isn't a path inside the project"));
+        }
+        let selection = match fragment {
+            None => FileSelection::Whole,
+            Some(fragment) => match fragment.strip_prefix("region=") {
+                Some(region) if !region.trim().is_empty() => {
+                    FileSelection::Region(region.trim().to_string())
+                }
+                Some(_) => return Err("missing region name".to_string()),
+                None => line_range(fragment)?,
+            },
+        };
+        Ok(FileEmbed {
+            path: path.into(),
+            selection,
+        })
+    }
+
+    pub fn extract<'a>(&self, contents: &'a str) -> Result<&'a str, String> {
+        let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+        let (start, end) = match &self.selection {
+            FileSelection::Whole => return Ok(contents),
+            FileSelection::Lines { start, end } if *end > lines.len() => {
+                return Err(format!(
+                    "lines {start}-{end} are out of range, the file has {} lines",
+                    lines.len()
+                ))
+            }
+            FileSelection::Lines { start, end } => (start - 1, *end),
+            FileSelection::Region(region) => region_lines(&lines, region)?,
+        };
+        let offset = |line: usize| lines[..line].iter().map(|line| line.len()).sum::<usize>();
+        Ok(&contents[offset(start)..offset(end)])
+    }
+}
+
+fn line_range(fragment: &str) -> Result<FileSelection, String> {
+    let line = |number: &str| {
+        number
+            .trim()
+            .trim_start_matches('L')
+            .parse::<usize>()
+            .ok()
+            .filter(|line| *line > 0)
+            .ok_or(format!("invalid line range `{fragment}`"))
+    };
+    if !fragment.starts_with('L') {
+        return Err(format!(
+            "expected a line range or a region instead of `{fragment}`"
+        ));
+    }
+    let (start, end) = match fragment.split_once('-') {
+        Some((start, end)) => (line(start)?, line(end)?),
+        None => (line(fragment)?, line(fragment)?),
+    };
+    if start > end {
+        return Err(format!("invalid line range `{fragment}`"));
+    }
+    Ok(FileSelection::Lines { start, end })
+}
+
+fn marker<'a>(line: &'a str, keyword: &str) -> Option<&'a str> {
+    line.split_once(keyword)
+        .map(|(_, rest)| rest.split_whitespace().next().unwrap_or_default())
+}
+
+// The lines between the region's markers, skipping over any regions nested inside it
+fn region_lines(lines: &[&str], region: &str) -> Result<(usize, usize), String> {
+    let start = lines
+        .iter()
+        .position(|line| marker(line, "#region") == Some(region))
+        .ok_or(format!("no region `{region}`"))?;
+    let mut depth = 0;
+    for (index, line) in lines.iter().enumerate().skip(start + 1) {
+        if marker(line, "#endregion").is_some() {
+            if depth == 0 {
+                return Ok((start + 1, index));
+            }
+            depth -= 1;
+        } else if marker(line, "#region").is_some() {
+            depth += 1;
+        }
+    }
+    Err(format!(
+        "region `{region}` is never closed with `#endregion`"
+    ))
+}
+/*@*/
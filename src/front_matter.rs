@@ -0,0 +1,91 @@
+use nom::Offset;
+use serde_json::{Map, Value};
+
+/*@[Front Matter|Impl]
+# Front Matter
+A block may start with metadata between `---` fences written in YAML or TOML, or between
+`+++` fences written in TOML. The metadata is split off the block before its markdown is
+parsed, so values may contain `\@` or braces, and it's stored on the block info, so templates
+and queries can use keys such as tags, status, owner or weight.
+*/
+pub struct FrontMatter<'a> {
+    // The source line opening the front matter, where errors in it are reported
+    pub opening_line: &'a str,
+    pub fence: &'static str,
+    pub body: String,
+    // The text from the end of the closing fence on
+    pub rest: &'a str,
+}
+
+impl FrontMatter<'_> {
+    pub fn metadata(&self) -> Result<Map<String, Value>, String> {
+        parse_front_matter(self.fence, &self.body)
+    }
+}
+
+// Front matter is split off the raw text of a block before links or markers in it are parsed.
+// `line_text` gives the text of a line without its comment prefix or decoration, or None for
+// a line that can't be part of the front matter, such as one closing the comment
+pub fn split_front_matter<'a>(
+    text: &'a str,
+    line_text: impl Fn(&'a str) -> Option<&'a str>,
+) -> Option<FrontMatter<'a>> {
+    let mut lines = text.split_inclusive('\n');
+    let opening_line = lines
+        .by_ref()
+        .find(|line| !line_text(line).is_some_and(|line| line.trim().is_empty()))?;
+    let opening_text = line_text(opening_line)?.trim();
+    let fence = ["---", "+++"]
+        .into_iter()
+        .find(|fence| *fence == opening_text)?;
+
+    let mut body_lines = vec![];
+    for line in lines {
+        let body_line = line_text(line)?;
+        if body_line.trim() == fence {
+            let fence_end = text.offset(line) + line.trim_end_matches(['\n', '\r']).len();
+            return Some(FrontMatter {
+                opening_line,
+                fence,
+                body: dedent(&body_lines),
+                rest: &text[fence_end..],
+            });
+        }
+        body_lines.push(body_line);
+    }
+    None
+}
+
+fn dedent(lines: &[&str]) -> String {
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or(line.trim_start()))
+        .collect()
+}
+
+pub fn parse_front_matter(fence: &str, body: &str) -> Result<Map<String, Value>, String> {
+    let metadata = if fence == "+++" {
+        toml::from_str::<Value>(body).map_err(|error| error.message().to_string())?
+    } else {
+        match serde_yaml::from_str::<Value>(body) {
+            Ok(metadata @ (Value::Object(_) | Value::Null)) => metadata,
+            yaml_result => match toml::from_str::<Value>(body) {
+                Ok(metadata) => metadata,
+                Err(_) => yaml_result.map_err(|error| error.to_string())?,
+            },
+        }
+    };
+
+    match metadata {
+        Value::Object(metadata) => Ok(metadata),
+        Value::Null => Ok(Map::new()),
+        _ => Err("front matter must be a map of keys to values".to_string()),
+    }
+}
+/*@*/
@@ -0,0 +1,403 @@
+use crate::config::LanguageConfig;
+use serde::{Deserialize, Serialize};
+
+/*@[Item Attachment|Impl]
+# Item Attachment
+With `attach_items` set for a language, a block without code of its own that is closed right
+above an item, such as a `fn`, `struct`, `enum`, `impl`, `class` or `def`, captures exactly
+that item along with its attributes or decorators. Items are found with a heuristic scan of
+the source rather than a full parser for each language: the item ends at its matching closing
+brace, or for a `def` or `class` whose header ends in `:` at the last line indented past it.
+Comments and string literals, including rust raw strings and python triple-quoted strings,
+are skipped while looking for either, and a header may span several lines. The signature of
+the item is stored on the block so templates can show its name, parameters and return type.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemSignature {
+    pub kind: String,
+    pub name: String,
+    pub params: Vec<String>,
+    pub returns: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item<'a> {
+    pub code: &'a str,
+    pub signature: ItemSignature,
+}
+
+const ITEM_KEYWORDS: [&str; 12] = [
+    "fn",
+    "struct",
+    "enum",
+    "union",
+    "impl",
+    "trait",
+    "mod",
+    "class",
+    "interface",
+    "def",
+    "function",
+    "func",
+];
+const FUNCTION_KEYWORDS: [&str; 4] = ["fn", "def", "function", "func"];
+const INDENTED_KEYWORDS: [&str; 2] = ["def", "class"];
+// Modifiers and the like are allowed before the keyword, up to this many words
+const MAX_KEYWORD_POSITION: usize = 6;
+
+// The item at the start of the text, skipping blank lines before it
+pub fn find_item<'a>(text: &'a str, language_config: &LanguageConfig) -> Option<Item<'a>> {
+    let item_start = line_start(text, text.len() - text.trim_start().len());
+    let mut keyword_line = item_start;
+    while is_attribute(text[keyword_line..].trim_start()) {
+        let attribute = keyword_line + indentation(&text[keyword_line..]);
+        keyword_line = balanced_line_end(text, attribute, language_config);
+    }
+    let (kind, keyword_start) = item_keyword(text, keyword_line)?;
+    let keyword_end = keyword_start + kind.len();
+
+    let indented = INDENTED_KEYWORDS.contains(&kind);
+    let (signature_end, item_end) = match header_end(text, keyword_end, language_config, indented)?
+    {
+        (index, b':') => (
+            index,
+            indented_end(text, item_start, index, language_config),
+        ),
+        (index, b'{') => (
+            index,
+            with_rest_of_line(text, matching_brace(text, index, language_config)?),
+        ),
+        (index, _) => (index, with_rest_of_line(text, index + 1)),
+    };
+    let signature_text = text[keyword_line..signature_end].trim();
+    Some(Item {
+        code: &text[item_start..item_end],
+        signature: signature(kind, &text[keyword_end..signature_end], signature_text),
+    })
+}
+
+// Takes the rest of the line along with the item when nothing else is on it
+fn with_rest_of_line(text: &str, item_end: usize) -> usize {
+    let line_end = text[item_end..]
+        .find('\n')
+        .map_or(text.len(), |end| item_end + end + 1);
+    match text[item_end..line_end].trim() {
+        "" | ";" => line_end,
+        _ => item_end,
+    }
+}
+
+pub(crate) fn is_attribute(line: &str) -> bool {
+    line.starts_with("#[") || line.starts_with('@')
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+fn line_start(text: &str, index: usize) -> usize {
+    text[..index].rfind('\n').map_or(0, |newline| newline + 1)
+}
+
+// The item keyword on the line, after any modifiers such as `pub(crate)`, `async` or `export`
+fn item_keyword(text: &str, line: usize) -> Option<(&'static str, usize)> {
+    let line_text = &text[line..text[line..].find('\n').map_or(text.len(), |end| line + end)];
+    let mut words = line_text.split_whitespace().take(MAX_KEYWORD_POSITION);
+    let word = words.find_map(|word| {
+        let identifier = word
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or_default();
+        ITEM_KEYWORDS
+            .iter()
+            .find(|keyword| **keyword == identifier)
+            .map(|keyword| (*keyword, word))
+            .or_else(|| word.contains(['=', '{', ';']).then_some(("", word)))
+    })?;
+    match word {
+        ("", _) => None,
+        (keyword, word) => Some((keyword, line + line_text.find(word)?)),
+    }
+}
+
+// Where a comment or literal starting at the index ends, if one starts there
+fn skip_ignored(text: &str, index: usize, language_config: &LanguageConfig) -> Option<usize> {
+    let rest = &text[index..];
+    let is_line_comment = language_config
+        .single_line_prefixes
+        .iter()
+        .any(|prefix| !prefix.is_empty() && rest.starts_with(prefix.as_str()));
+    if is_line_comment {
+        return Some(rest.find('\n').map_or(text.len(), |end| index + end));
+    }
+    let (start, end) = (
+        language_config.multiline_start.as_str(),
+        language_config.multiline_end.as_str(),
+    );
+    if !start.is_empty() && !end.is_empty() && rest.starts_with(start) {
+        let comment_end = rest[start.len()..].find(end);
+        return Some(comment_end.map_or(text.len(), |offset| {
+            index + start.len() + offset + end.len()
+        }));
+    }
+    if let Some(quotes) = ["\"\"\"", "'''"]
+        .into_iter()
+        .find(|quotes| rest.starts_with(quotes))
+    {
+        let literal_end = rest[3..].find(quotes);
+        return Some(literal_end.map_or(text.len(), |offset| index + 6 + offset));
+    }
+    if language_config.language == "rust" {
+        if let Some(end) = raw_string_end(text, index) {
+            return Some(end);
+        }
+    }
+    match rest.as_bytes().first()? {
+        b'"' | b'`' => Some(literal_end(text, index, true)),
+        b'\'' => {
+            let mut chars = rest[1..].chars();
+            match (chars.next(), chars.next()) {
+                (Some('\\'), _) => Some(literal_end(text, index, false)),
+                (Some(character), Some('\'')) => Some(index + 2 + character.len_utf8()),
+                // Anything else is a lifetime in rust
+                _ if language_config.language == "rust" => None,
+                _ => Some(literal_end(text, index, false)),
+            }
+        }
+        _ => None,
+    }
+}
+
+// Raw strings such as `r#"..."#` end at a quote followed by as many `#` as they started with,
+// and backslashes don't escape anything in them
+fn raw_string_end(text: &str, index: usize) -> Option<usize> {
+    let rest = &text[index..];
+    let in_identifier = text[..index]
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_');
+    if in_identifier {
+        return None;
+    }
+    let after_prefix = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+    let hashes = after_prefix.len() - after_prefix.trim_start_matches('#').len();
+    let body = after_prefix[hashes..].strip_prefix('"')?;
+    let closing = format!("\"{}", "#".repeat(hashes));
+    let body_start = text.len() - body.len();
+    Some(
+        body.find(&closing)
+            .map_or(text.len(), |end| body_start + end + closing.len()),
+    )
+}
+
+fn literal_end(text: &str, index: usize, multiline: bool) -> usize {
+    let quote = text.as_bytes()[index];
+    let mut escaped = false;
+    for (offset, byte) in text.as_bytes()[index + 1..].iter().enumerate() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            b'\n' if !multiline => return index + 1 + offset,
+            byte if *byte == quote => return index + 2 + offset,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+// Calls the visitor with each byte outside of comments and literals, until it returns a result
+fn scan<T>(
+    text: &str,
+    from: usize,
+    language_config: &LanguageConfig,
+    mut visit: impl FnMut(usize, u8) -> Option<T>,
+) -> Option<T> {
+    let mut index = from;
+    while index < text.len() {
+        if let Some(end) = skip_ignored(text, index, language_config).filter(|end| *end > index) {
+            index = end;
+            continue;
+        }
+        if let Some(result) = visit(index, text.as_bytes()[index]) {
+            return Some(result);
+        }
+        // Comments and literals can only start on a character boundary
+        index += text[index..].chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+// The start of the next line after the index, ignoring line breaks inside brackets
+fn balanced_line_end(text: &str, from: usize, language_config: &LanguageConfig) -> usize {
+    let mut depth = 0usize;
+    scan(text, from, language_config, |index, byte| {
+        match byte {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth = depth.saturating_sub(1),
+            b'\n' if depth == 0 => return Some(index + 1),
+            _ => {}
+        }
+        None
+    })
+    .unwrap_or(text.len())
+}
+
+// Where the item's header ends, at the `{` opening its body, a `;`, or a `:` ending the line
+fn header_end(
+    text: &str,
+    from: usize,
+    language_config: &LanguageConfig,
+    indented: bool,
+) -> Option<(usize, u8)> {
+    let mut depth = 0usize;
+    scan(text, from, language_config, |index, byte| {
+        match byte {
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b'{' | b';' if depth == 0 => return Some((index, byte)),
+            b':' if depth == 0 && indented && !text[index..].starts_with("::") => {
+                return Some((index, byte))
+            }
+            _ => {}
+        }
+        None
+    })
+}
+
+fn matching_brace(text: &str, open: usize, language_config: &LanguageConfig) -> Option<usize> {
+    let mut depth = 0usize;
+    scan(text, open, language_config, |index, byte| {
+        match byte {
+            b'{' => depth += 1,
+            b'}' if depth == 1 => return Some(index + 1),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        None
+    })
+}
+
+// The end of the last line after the header that is indented past the item. Only lines
+// starting outside of literals count, so the lines of a multiline string stay in the item
+fn indented_end(
+    text: &str,
+    item_start: usize,
+    colon: usize,
+    language_config: &LanguageConfig,
+) -> usize {
+    let item_indentation = indentation(&text[item_start..]);
+    let mut end = text[colon..]
+        .find('\n')
+        .map_or(text.len(), |newline| colon + newline + 1);
+    let mut line_start = end;
+    // Whether the line is part of the item, or None for a blank line
+    let in_item = |line: &str| match line.trim().is_empty() {
+        true => None,
+        false => Some(indentation(line) > item_indentation),
+    };
+    let body_end = scan(text, end, language_config, |index, byte| {
+        if byte != b'\n' {
+            return None;
+        }
+        match in_item(&text[line_start..index]) {
+            Some(false) => return Some(end),
+            Some(true) => end = index + 1,
+            None => {}
+        }
+        line_start = index + 1;
+        None
+    });
+    match body_end {
+        Some(body_end) => body_end,
+        None if in_item(&text[line_start..]) == Some(true) => text.len(),
+        None => end,
+    }
+}
+
+fn signature(kind: &str, after_keyword: &str, text: &str) -> ItemSignature {
+    let after_keyword = after_keyword.trim_start();
+    let name = match kind {
+        "impl" => before_where(skip_generics(after_keyword)),
+        _ => {
+            let end = after_keyword
+                .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '$')
+                .unwrap_or(after_keyword.len());
+            after_keyword[..end].to_string()
+        }
+    };
+    let (params, returns) = match FUNCTION_KEYWORDS.contains(&kind) {
+        true => parameters(skip_generics(&after_keyword[name.len()..])),
+        false => (vec![], None),
+    };
+    ItemSignature {
+        kind: kind.to_string(),
+        name,
+        params,
+        returns,
+        text: text.to_string(),
+    }
+}
+
+// The words before a `where` clause, with whitespace collapsed
+fn before_where(text: &str) -> String {
+    text.split_whitespace()
+        .take_while(|word| *word != "where")
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn skip_generics(text: &str) -> &str {
+    if !text.starts_with('<') {
+        return text;
+    }
+    let mut depth = 0;
+    for (index, character) in text.char_indices() {
+        match character {
+            '<' => depth += 1,
+            '>' if depth == 1 => return &text[index + 1..],
+            '>' => depth -= 1,
+            _ => {}
+        }
+    }
+    text
+}
+
+// The parameters between the first pair of parentheses, split on top level commas, and the
+// type after a `->` or `:` that follows them
+fn parameters(text: &str) -> (Vec<String>, Option<String>) {
+    let Some(open) = text.find('(') else {
+        return (vec![], None);
+    };
+    let mut params = vec![];
+    let mut depth = 0;
+    let mut param_start = open + 1;
+    let mut previous = ' ';
+    for (index, character) in text.char_indices().skip_while(|(index, _)| *index < open) {
+        match character {
+            '(' | '[' | '{' | '<' => depth += 1,
+            // The `>` of an arrow doesn't close anything
+            '>' if previous == '-' || previous == '=' => {}
+            ')' if depth == 1 => {
+                params.push(text[param_start..index].trim().to_string());
+                let rest = text[index + 1..].trim();
+                let returns = rest
+                    .strip_prefix("->")
+                    .or_else(|| rest.strip_prefix(':'))
+                    .map(before_where)
+                    .filter(|returns| !returns.is_empty());
+                params.retain(|param| !param.is_empty());
+                return (params, returns);
+            }
+            ')' | ']' | '}' | '>' => depth -= 1,
+            ',' if depth == 1 => {
+                params.push(text[param_start..index].trim().to_string());
+                param_start = index + 1;
+            }
+            _ => {}
+        }
+        previous = character;
+    }
+    (vec![], None)
+}
+/*@*/
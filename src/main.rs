@@ -5,4 +5,7 @@ async fn main() {
     let result = process_cli().await;
     //Error Handling
     println!("{:?}", result);
+    if result.is_err() {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,271 @@
+use crate::common::{AnubisError, Block, BlockContent, BlockInfo, BlockSegment};
+use crate::config::{AnubisConfig, LanguageConfig};
+use crate::diagnostics::Diagnostic;
+use crate::extension::ContentExtension;
+use crate::parser_core::{file_parser, whole_file_content, whole_file_name, SourceIndex};
+use nom::Parser;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/*@[Notebooks|Impl]
+# Notebooks
+A language with `notebook` set, usually `ipynb`, is read as a Jupyter notebook instead of
+plain text. The cells are laid out one after another, so locations count the lines of the
+cells rather than of the JSON. Markers in markdown cells are parsed with the `md` language
+config and markers in code cells with the config for the kernel's file extension, each cell
+on its own. Unless `markers_only` is set the notebook itself becomes a block as well, named
+like a whole markdown file and rendered with the template of `whole_file`, if any. Its
+markdown cells are parsed for links and embeds like a whole markdown file and its code cells
+are code, followed by their text outputs when `outputs` is set.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellKind {
+    Markdown,
+    Code,
+    Raw,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotebookCell {
+    pub kind: CellKind,
+    // Where the cell's source is in the notebook's text
+    pub range: Range<usize>,
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notebook {
+    pub text: String,
+    pub cells: Vec<NotebookCell>,
+    pub code_extension: String,
+}
+
+#[derive(Deserialize)]
+struct NotebookJson {
+    cells: Vec<CellJson>,
+    #[serde(default)]
+    metadata: NotebookMetadata,
+}
+
+#[derive(Deserialize, Default)]
+struct NotebookMetadata {
+    #[serde(default)]
+    language_info: Option<LanguageInfo>,
+}
+
+#[derive(Deserialize)]
+struct LanguageInfo {
+    #[serde(default)]
+    file_extension: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CellJson {
+    cell_type: String,
+    source: MultilineText,
+    #[serde(default)]
+    outputs: Vec<OutputJson>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "output_type", rename_all = "snake_case")]
+enum OutputJson {
+    Stream {
+        text: MultilineText,
+    },
+    ExecuteResult {
+        data: Map<String, Value>,
+    },
+    DisplayData {
+        data: Map<String, Value>,
+    },
+    Error {
+        ename: String,
+        evalue: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+// Notebooks store text either as one string or as a list of lines
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MultilineText {
+    Text(String),
+    Lines(Vec<String>),
+}
+
+impl MultilineText {
+    fn join(self) -> String {
+        match self {
+            MultilineText::Text(text) => text,
+            MultilineText::Lines(lines) => lines.concat(),
+        }
+    }
+}
+
+impl OutputJson {
+    fn text(self) -> Option<String> {
+        match self {
+            OutputJson::Stream { text } => Some(text.join()),
+            OutputJson::ExecuteResult { mut data } | OutputJson::DisplayData { mut data } => {
+                let text = serde_json::from_value(data.remove("text/plain")?).ok()?;
+                Some(MultilineText::join(text))
+            }
+            OutputJson::Error { ename, evalue } => Some(format!("{ename}: {evalue}")),
+            OutputJson::Other => None,
+        }
+    }
+}
+
+impl Notebook {
+    pub fn parse(json: &str) -> Result<Self, serde_json::Error> {
+        let notebook: NotebookJson = serde_json::from_str(json)?;
+        let code_extension = notebook
+            .metadata
+            .language_info
+            .and_then(|info| info.file_extension)
+            .map(|extension| extension.trim_start_matches('.').to_string())
+            .unwrap_or("py".to_string());
+        let mut text = String::new();
+        let mut cells = vec![];
+        for cell in notebook.cells {
+            let kind = match cell.cell_type.as_str() {
+                "markdown" => CellKind::Markdown,
+                "code" => CellKind::Code,
+                _ => CellKind::Raw,
+            };
+            let start = text.len();
+            text.push_str(&cell.source.join());
+            let range = start..text.len();
+            // Each cell starts on a line of its own
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            let outputs = cell.outputs.into_iter().filter_map(OutputJson::text);
+            cells.push(NotebookCell {
+                kind,
+                range,
+                outputs: outputs.collect(),
+            });
+        }
+        Ok(Notebook {
+            text,
+            cells,
+            code_extension,
+        })
+    }
+
+    pub fn blocks<'a>(
+        &self,
+        config: &AnubisConfig,
+        notebook_config: &LanguageConfig,
+        extensions: &[Box<dyn ContentExtension>],
+        source_index: &'a SourceIndex<'a>,
+    ) -> Result<(Vec<Block<'static>>, Vec<Diagnostic>), AnubisError> {
+        // Cells are parsed for markers only, so each of them isn't a whole file of its own
+        let cell_config = |extension: &str| {
+            config
+                .language_configs
+                .get(extension)
+                .map(|language_config| LanguageConfig {
+                    whole_file: None,
+                    notebook: None,
+                    ..language_config.clone()
+                })
+        };
+        let markdown_config = cell_config("md");
+        let code_config = cell_config(&self.code_extension);
+
+        let mut blocks = vec![];
+        let mut diagnostics = vec![];
+        for cell in &self.cells {
+            let language_config = match cell.kind {
+                CellKind::Markdown => markdown_config.as_ref(),
+                CellKind::Code => code_config.as_ref(),
+                CellKind::Raw => None,
+            };
+            let Some(language_config) = language_config else {
+                continue;
+            };
+            let (_, (cell_blocks, cell_diagnostics)) =
+                file_parser(language_config, extensions, source_index)
+                    .parse(&source_index.source[cell.range.clone()])
+                    .map_err(|error| AnubisError::ParsingError(error.to_string()))?;
+            blocks.extend(cell_blocks.into_iter().map(Block::into_owned));
+            diagnostics.extend(cell_diagnostics);
+        }
+        let notebook = notebook_config.notebook.clone().unwrap_or_default();
+        if !notebook.markers_only {
+            let whole_file = notebook_config.whole_file.clone().unwrap_or_default();
+            // Links and embeds in markdown cells are parsed like in a whole markdown file
+            let markdown_config = markdown_config.as_ref().unwrap_or(notebook_config);
+            let mut markdown = whole_file_content(markdown_config, extensions, source_index);
+            let mut markdown_cell = |source| match markdown.parse(source) {
+                Ok((_, segments)) => segments,
+                Err(error) => {
+                    if let nom::Err::Failure(error) | nom::Err::Error(error) = &error {
+                        diagnostics.push(source_index.diagnostic(error));
+                    }
+                    vec![BlockSegment {
+                        content: BlockContent::Markdown(Cow::Borrowed(source)),
+                        span: source_index.span(source),
+                    }]
+                }
+            };
+            let mut block = self.notebook_block(
+                &whole_file.template,
+                notebook.outputs,
+                source_index,
+                &mut markdown_cell,
+            );
+            block.info.name = whole_file_name(&block, &source_index.path);
+            blocks.insert(0, block.into_owned());
+        }
+        Ok((blocks, diagnostics))
+    }
+
+    fn notebook_block<'a>(
+        &self,
+        template: &str,
+        outputs: bool,
+        source_index: &SourceIndex<'a>,
+        markdown_cell: &mut impl FnMut(&'a str) -> Vec<BlockSegment<'a>>,
+    ) -> Block<'a> {
+        let mut content = vec![];
+        for cell in &self.cells {
+            let source = &source_index.source[cell.range.clone()];
+            let span = source_index.span(source);
+            match cell.kind {
+                CellKind::Markdown => content.extend(markdown_cell(source)),
+                CellKind::Code => content.push(BlockSegment {
+                    content: BlockContent::Code(Cow::Borrowed(source)),
+                    span,
+                }),
+                CellKind::Raw => continue,
+            }
+            let outputs = cell.outputs.iter().filter(|_| outputs);
+            content.extend(outputs.map(|output| BlockSegment {
+                content: BlockContent::Code(Cow::Owned(output.clone())),
+                span,
+            }));
+        }
+        Block {
+            info: BlockInfo {
+                name: String::new(),
+                template_name: template.to_string(),
+                args: Map::new(),
+                parent: None,
+                metadata: Map::new(),
+                location: source_index.location(source_index.source),
+                continuation: false,
+                fragments: vec![],
+                signature: None,
+            },
+            content,
+        }
+    }
+}
+/*@*/
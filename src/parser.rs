@@ -1,41 +1,445 @@
-use crate::common::{collect_all_files, read_file, remove_ignored_files, Anubis};
-use crate::parser_core::file_parser;
+use crate::common::{
+    collect_all_files, remove_ignored_files, Anubis, AnubisError, Block, BlockContent,
+    SourceLocation, Span,
+};
+use crate::config::{AnubisConfig, LanguageConfig, UnconfiguredPolicy};
+use crate::db::FileRecord;
+use crate::diagnostics::{sort_diagnostics, Diagnostic, Severity};
+use crate::encoding::{read_source, read_text, SourceText};
+use crate::extension::ContentExtension;
+use crate::notebook::Notebook;
+use crate::parser_core::{file_parser, SourceIndex};
+use encoding_rs::Encoding;
 use nom::Parser;
+use rayon::{prelude::*, ThreadPoolBuilder};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
 use std::path::Path;
+use std::time::SystemTime;
 use std::{collections::HashSet, path::PathBuf};
 
+/*@[Parse Summary|Impl]
+# Parse Summary
+The diagnostics of every parsed file, along with how many files had to be parsed again.
+Files that are unchanged since the last run keep their blocks and diagnostics from the
+database, and blocks from files that no longer exist are removed. Files without a language
+config are listed by extension when the `unconfigured_files` policy is `warn`. Duplicate
+blocks reported as errors under the `error` policy are counted, so the parse can fail.
+*/
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParseSummary {
+    pub diagnostics: Vec<Diagnostic>,
+    pub parsed_files: usize,
+    pub unchanged_files: usize,
+    pub removed_files: usize,
+    pub skipped_files: usize,
+    pub unconfigured_files: BTreeMap<String, Vec<PathBuf>>,
+    pub duplicate_errors: usize,
+}
+
+impl fmt::Display for ParseSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Parsed {} of {} files ({} unchanged, {} removed)",
+            self.parsed_files,
+            self.parsed_files + self.unchanged_files,
+            self.unchanged_files,
+            self.removed_files
+        )?;
+        if self.skipped_files > 0 {
+            write!(f, ", skipped {} binary files", self.skipped_files)?;
+        }
+        let unconfigured = self
+            .unconfigured_files
+            .values()
+            .map(Vec::len)
+            .sum::<usize>();
+        if unconfigured > 0 {
+            write!(
+                f,
+                "\nSkipped {unconfigured} files without a language config:"
+            )?;
+        }
+        for (extension, files) in &self.unconfigured_files {
+            let files: Vec<String> = files
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect();
+            write!(f, "\n  {extension} ({}): {}", files.len(), files.join(", "))?;
+        }
+        Ok(())
+    }
+}
+/*@*/
+
 pub trait AnubisParser {
-    fn parse(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    fn parse(&mut self) -> Result<ParseSummary, Box<dyn std::error::Error>>;
     fn parse_files(
         &mut self,
         file_list: HashSet<PathBuf>,
-    ) -> Result<(), Box<dyn std::error::Error>>;
-    fn parse_file(&mut self, file_path: &Path) -> Result<(), Box<dyn std::error::Error>>;
+    ) -> Result<ParseSummary, Box<dyn std::error::Error>>;
+    fn parse_file(&mut self, file_path: &Path) -> Result<bool, Box<dyn std::error::Error>>;
+}
+
+fn source_path(file_path: &Path) -> &Path {
+    file_path.strip_prefix("./").unwrap_or(file_path)
+}
+
+fn sha256(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    parts.iter().for_each(|part| hasher.update(part));
+    format!("{:x}", hasher.finalize())
+}
+
+fn file_hash(path: &Path) -> String {
+    fs::read(path)
+        .map(|contents| sha256(&[&contents]))
+        .unwrap_or_default()
+}
+
+// Files embedded by the blocks are checked while parsing, and hashed so the blocks are
+// parsed again when one of them changes
+fn embedded_files(
+    blocks: &[Block],
+    source_index: &SourceIndex,
+    encoding: &'static Encoding,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> BTreeMap<PathBuf, String> {
+    let mut dependencies = BTreeMap::new();
+    let segments = blocks.iter().flat_map(|block| &block.content);
+    for segment in segments {
+        let BlockContent::File(file) = &segment.content else {
+            continue;
+        };
+        dependencies.insert(file.path.clone(), file_hash(&file.path));
+        let error = match read_text(&file.path, encoding) {
+            Ok(contents) => file.extract(&contents).err(),
+            Err(error) => Some(format!("could not be read: {error}")),
+        };
+        if let Some(error) = error {
+            let snippet = source_index.line_text(segment.span.start_line);
+            diagnostics.push(Diagnostic::error(
+                format!("embedded file `{}` {error}", file.path.display()),
+                source_index.location(snippet),
+                snippet.to_string(),
+            ));
+        }
+    }
+    dependencies
+}
+
+// Files are parsed again whenever the language config, the fallback encoding, the registered
+// extensions or the parser itself changes
+fn config_hash(
+    lang_configs: &[&LanguageConfig],
+    encoding: &'static Encoding,
+    extensions: &[Box<dyn ContentExtension>],
+) -> Result<String, serde_json::Error> {
+    let extensions: Vec<[&str; 3]> = extensions
+        .iter()
+        .map(|extension| [extension.name(), extension.start(), extension.end()])
+        .collect();
+    Ok(sha256(&[
+        env!("CARGO_PKG_VERSION").as_bytes(),
+        &serde_json::to_vec(lang_configs)?,
+        encoding.name().as_bytes(),
+        &serde_json::to_vec(&extensions)?,
+    ]))
+}
+
+/*@[Parallel Parsing|Impl]
+# Parallel Parsing
+Files are read and parsed on a thread pool without touching the database, then merged into
+it one at a time in order of their path, so the result doesn't depend on the number of
+threads. The pool size comes from the `jobs` config or the `--jobs` flag, and defaults to
+the number of CPUs. A file that can't be read, such as a malformed notebook, is reported
+and skipped instead of stopping the parse.
+*/
+enum FileParse {
+    Unchanged,
+    Touched(Option<SystemTime>),
+    Parsed(FileRecord, Vec<Block<'static>>),
+    Binary,
+    Unconfigured,
+    Unreadable(Diagnostic),
+}
+
+// A file that can't be read is reported and skipped, so the other files are still parsed
+fn unreadable(
+    file_path: &Path,
+    message: String,
+    line: usize,
+    column: usize,
+    snippet: &str,
+) -> FileParse {
+    let location = SourceLocation {
+        path: source_path(file_path).to_path_buf(),
+        span: Span {
+            start_line: line,
+            start_column: column,
+            end_line: line,
+            end_column: column,
+            ..Span::default()
+        },
+    };
+    FileParse::Unreadable(Diagnostic::error(message, location, snippet.to_string()))
 }
 
+// The key files without a language config are grouped under in the parse summary
+fn unconfigured_extension(file_path: &Path) -> String {
+    match file_path.extension() {
+        Some(extension) => format!(".{}", extension.to_string_lossy()),
+        None => "no extension".to_string(),
+    }
+}
+
+fn parse_source_file(
+    config: &AnubisConfig,
+    extensions: &[Box<dyn ContentExtension>],
+    previous: Option<&FileRecord>,
+    file_path: &Path,
+) -> Result<FileParse, AnubisError> {
+    let lang_config = match config.get_language_config(file_path) {
+        Ok(lang_config) => lang_config,
+        Err(error) if config.unconfigured_files == UnconfiguredPolicy::Error => {
+            return Err(AnubisError::ConfigError(error.to_string()))
+        }
+        Err(_) => return Ok(FileParse::Unconfigured),
+    };
+    let source_path = source_path(file_path);
+    let encoding = config.fallback_encoding()?;
+    // The cells of a notebook are parsed with the configs of other languages
+    let lang_configs: Vec<&LanguageConfig> = match lang_config.notebook {
+        Some(_) => config
+            .language_configs
+            .iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_values()
+            .collect(),
+        None => vec![lang_config],
+    };
+    let config_hash = config_hash(&lang_configs, encoding, extensions)
+        .map_err(|error| AnubisError::ConfigError(error.to_string()))?;
+    let modified = fs::metadata(file_path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let previous = previous.filter(|record| {
+        record.config_hash == config_hash
+            && record
+                .dependencies
+                .iter()
+                .all(|(path, hash)| file_hash(path) == *hash)
+    });
+    if previous.is_some_and(|record| modified.is_some() && record.modified == modified) {
+        return Ok(FileParse::Unchanged);
+    }
+
+    let source_text = match read_source(file_path, encoding) {
+        Ok(source_text) => source_text,
+        Err(error) => {
+            let message = format!("could not read `{}`: {error}", source_path.display());
+            return Ok(unreadable(file_path, message, 1, 1, ""));
+        }
+    };
+    let SourceText::Text(file_contents) = source_text else {
+        return Ok(FileParse::Binary);
+    };
+    let content_hash = sha256(&[file_contents.as_bytes()]);
+    if previous.is_some_and(|record| record.content_hash == content_hash) {
+        return Ok(FileParse::Touched(modified));
+    }
+
+    let notebook = match lang_config.notebook {
+        Some(_) => match Notebook::parse(&file_contents) {
+            Ok(notebook) => Some(notebook),
+            Err(error) => {
+                let (line, column) = (error.line().max(1), error.column().max(1));
+                let snippet = file_contents.lines().nth(line - 1).unwrap_or_default();
+                let message = format!(
+                    "could not read notebook `{}`: {error}",
+                    source_path.display()
+                );
+                return Ok(unreadable(file_path, message, line, column, snippet));
+            }
+        },
+        None => None,
+    };
+    let source = notebook
+        .as_ref()
+        .map_or(file_contents.as_str(), |notebook| notebook.text.as_str());
+    let source_index = SourceIndex::new(source_path, source);
+    let (blocks, mut diagnostics) = match &notebook {
+        Some(notebook) => notebook.blocks(config, lang_config, extensions, &source_index)?,
+        None => {
+            let (_, (blocks, diagnostics)) = file_parser(lang_config, extensions, &source_index)
+                .parse(source)
+                .map_err(|error| AnubisError::ParsingError(error.to_string()))?;
+            (
+                blocks.into_iter().map(Block::into_owned).collect(),
+                diagnostics,
+            )
+        }
+    };
+    let dependencies = embedded_files(&blocks, &source_index, encoding, &mut diagnostics);
+    let record = FileRecord {
+        content_hash,
+        config_hash,
+        modified,
+        blocks: blocks.iter().map(|block| block.info.name.clone()).collect(),
+        diagnostics,
+        dependencies,
+    };
+    Ok(FileParse::Parsed(record, blocks))
+}
+
+impl Anubis {
+    fn merge_file(
+        &mut self,
+        file_path: &Path,
+        file_parse: FileParse,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let source_path = source_path(file_path);
+        match file_parse {
+            FileParse::Unchanged => Ok(false),
+            FileParse::Touched(modified) => {
+                if let Some(record) = self.database.file_db.get_mut(source_path) {
+                    record.modified = modified;
+                }
+                Ok(false)
+            }
+            FileParse::Parsed(record, blocks) => {
+                let lang_config = self.config.get_language_config(file_path)?;
+                self.database.remove_file(source_path);
+                self.database.insert_blocks(blocks, lang_config);
+                self.database.insert_file(source_path.to_path_buf(), record);
+                Ok(true)
+            }
+            // A file that is no longer parsed only changes the database if it was parsed before
+            FileParse::Binary | FileParse::Unconfigured | FileParse::Unreadable(_) => {
+                let parsed_before = self.database.get_file(source_path).is_some();
+                self.database.remove_file(source_path);
+                Ok(parsed_before)
+            }
+        }
+    }
+}
+/*@*/
+
 impl AnubisParser for Anubis {
-    fn parse(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn parse(&mut self) -> Result<ParseSummary, Box<dyn std::error::Error>> {
         let mut file_list = collect_all_files();
         let ignore_glob = self.config.generate_ignore_glob()?;
         remove_ignored_files(&mut file_list, ignore_glob);
-        self.parse_files(file_list)?;
+        let summary = self.parse_files(file_list)?;
         self.database.save("./anubis.db")?;
-        Ok(())
+        Ok(summary)
     }
 
     fn parse_files(
         &mut self,
         file_list: HashSet<PathBuf>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        file_list.iter().try_for_each(|file| self.parse_file(file))
+    ) -> Result<ParseSummary, Box<dyn std::error::Error>> {
+        let mut summary = ParseSummary::default();
+        let source_paths: HashSet<&Path> = file_list.iter().map(|file| source_path(file)).collect();
+        let removed_files: Vec<PathBuf> = self
+            .database
+            .file_db
+            .keys()
+            .filter(|path| !source_paths.contains(path.as_path()))
+            .cloned()
+            .collect();
+        for path in removed_files {
+            self.database.remove_file(&path);
+            summary.removed_files += 1;
+        }
+
+        let mut files: Vec<&PathBuf> = file_list.iter().collect();
+        files.sort_by_key(|file| source_path(file));
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(self.config.jobs.unwrap_or(0))
+            .build()?;
+        let (config, extensions, database) = (&self.config, &self.extensions, &self.database);
+        let file_parses: Vec<Result<FileParse, AnubisError>> = pool.install(|| {
+            files
+                .par_iter()
+                .map(|file| {
+                    let previous = database.get_file(source_path(file));
+                    parse_source_file(config, extensions, previous, file)
+                })
+                .collect()
+        });
+
+        for (file, file_parse) in files.into_iter().zip(file_parses) {
+            match file_parse? {
+                FileParse::Binary => {
+                    summary.skipped_files += 1;
+                    if self.merge_file(file, FileParse::Binary)? {
+                        summary.removed_files += 1;
+                    }
+                }
+                FileParse::Unreadable(diagnostic) => {
+                    summary.diagnostics.push(diagnostic.clone());
+                    if self.merge_file(file, FileParse::Unreadable(diagnostic))? {
+                        summary.removed_files += 1;
+                    }
+                }
+                FileParse::Unconfigured => {
+                    if self.config.unconfigured_files == UnconfiguredPolicy::Warn {
+                        summary
+                            .unconfigured_files
+                            .entry(unconfigured_extension(file))
+                            .or_default()
+                            .push(source_path(file).to_path_buf());
+                    }
+                    if self.merge_file(file, FileParse::Unconfigured)? {
+                        summary.removed_files += 1;
+                    }
+                }
+                file_parse => {
+                    if self.merge_file(file, file_parse)? {
+                        summary.parsed_files += 1;
+                    } else {
+                        summary.unchanged_files += 1;
+                    }
+                }
+            }
+            if let Some(record) = self.database.get_file(source_path(file)) {
+                summary
+                    .diagnostics
+                    .extend(record.diagnostics.iter().cloned());
+            }
+        }
+
+        let duplicates = self
+            .database
+            .resolve_duplicates(self.config.duplicate_blocks);
+        summary.duplicate_errors = duplicates
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count();
+        summary.diagnostics.extend(duplicates);
+        summary.diagnostics.extend(self.database.merge_fragments());
+        if summary.parsed_files + summary.removed_files > 0 {
+            self.database.rebuild_relations();
+        }
+        sort_diagnostics(&mut summary.diagnostics);
+        Ok(summary)
     }
 
-    fn parse_file(&mut self, file_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        let lang_config = self.config.get_language_config(file_path)?;
-        let file_contents = read_file(file_path)?;
-        if let Ok(result) = file_parser(lang_config).parse(&file_contents) {
-            self.database.insert_blocks(result.1, lang_config);
+    // Returns whether the file had to be parsed, or was unchanged since the last parse
+    fn parse_file(&mut self, file_path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+        let previous = self.database.get_file(source_path(file_path));
+        let file_parse = parse_source_file(&self.config, &self.extensions, previous, file_path)?;
+        let parsed = self.merge_file(file_path, file_parse)?;
+        if parsed {
+            self.database
+                .resolve_duplicates(self.config.duplicate_blocks);
+            self.database.merge_fragments();
+            self.database.rebuild_relations();
         }
-        Ok(())
+        Ok(parsed)
     }
 }
@@ -1,132 +1,1366 @@
-use crate::common::{Block, BlockContent, BlockInfo};
-use crate::config::LanguageConfig;
-use nom::character::anychar;
+use crate::common::{
+    Block, BlockContent, BlockInfo, BlockLink, BlockSegment, SourceLocation, Span,
+};
+use crate::config::{LanguageConfig, WholeFile};
+use crate::diagnostics::Diagnostic;
+use crate::doc_import::import_doc_comments;
+use crate::extension::{ContentExtension, CustomContent};
+use crate::file_embed::FileEmbed;
+use crate::front_matter::{split_front_matter, FrontMatter};
+use crate::item::find_item;
 use nom::sequence::delimited;
-use nom::IResult;
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag, take_until},
+    bytes::complete::{escaped_transform, is_not, tag, take_until, take_while1},
     character::complete::{char, multispace0},
-    combinator::{peek, value},
-    error::{self, ParseError},
-    multi::{many1, many_till},
-    sequence::{pair, separated_pair},
+    combinator::{all_consuming, consumed, cut, opt, peek, value, verify},
+    error::{context, ContextError, ErrorKind, FromExternalError, ParseError},
+    multi::{many0, many1, separated_list0},
+    number::complete::recognize_float,
+    sequence::{pair, preceded, separated_pair, terminated},
     Parser,
 };
+use nom::{IResult, Offset};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
 
-fn block_name(i: &str) -> IResult<&str, &str> {
-    ws(is_not("|")).parse(i)
+/*@[Source Index|Impl]
+# Source Index
+Maps slices of a source file back to their byte offsets, lines and columns,
+so that every parsed block can record where it was defined.
+*/
+pub struct SourceIndex<'a> {
+    pub path: PathBuf,
+    pub source: &'a str,
+    line_starts: Vec<usize>,
 }
 
-fn template_name(i: &str) -> IResult<&str, &str> {
-    ws(is_not("]")).parse(i)
+impl<'a> SourceIndex<'a> {
+    pub fn new(path: &Path, source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        SourceIndex {
+            path: path.to_path_buf(),
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self
+            .line_starts
+            .partition_point(|line_start| *line_start <= offset)
+            - 1;
+        let column = self.source[self.line_starts[line]..offset].chars().count();
+        (line + 1, column + 1)
+    }
+
+    pub fn span(&self, slice: &str) -> Span {
+        let start = self.source.offset(slice);
+        let end = start + slice.len();
+        let (start_line, start_column) = self.line_column(start);
+        let (end_line, end_column) = self.line_column(end);
+        Span {
+            start,
+            end,
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+        }
+    }
+
+    pub fn location(&self, slice: &str) -> SourceLocation {
+        SourceLocation {
+            path: self.path.clone(),
+            span: self.span(slice),
+        }
+    }
+
+    pub fn line_text(&self, line: usize) -> &'a str {
+        let line_start = self.line_starts[line - 1];
+        let line_end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |next_line_start| next_line_start - 1);
+        self.source[line_start..line_end].trim_end_matches('\r')
+    }
+
+    pub fn diagnostic(&self, error: &AnubisParseError<'a>) -> Diagnostic {
+        let found = match error.input.chars().next() {
+            Some(character) if character.is_control() => {
+                format!("`{}`", character.escape_debug())
+            }
+            Some(character) => format!("`{character}`"),
+            None => "end of file".to_string(),
+        };
+        let message = match error.expected {
+            Some(expected) => format!("expected {expected}, found {found}"),
+            None => format!("unexpected {found}"),
+        };
+        let location = self.location(&error.input[..0]);
+        let snippet = self.line_text(location.span.start_line).to_string();
+        Diagnostic::error(message, location, snippet)
+    }
 }
+/*@*/
 
-fn block_header(i: &str) -> IResult<&str, BlockInfo> {
-    let (not_matched, (block_name, template_name)) = delimited(
-        char('['),
-        separated_pair(block_name, char('|'), template_name),
-        char(']'),
-    )
-    .parse(i)?;
+/*@[Anubis Parse Error|Impl]
+# Anubis Parse Error
+The nom error type used by the block grammar. It remembers the innermost
+context that failed, so diagnostics can say what was expected.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnubisParseError<'a> {
+    pub input: &'a str,
+    pub kind: ErrorKind,
+    pub expected: Option<&'static str>,
+}
 
-    Ok((
-        not_matched,
-        BlockInfo {
-            name: block_name.to_string(),
-            template_name: template_name.to_string(),
-        },
-    ))
+impl<'a> ParseError<&'a str> for AnubisParseError<'a> {
+    fn from_error_kind(input: &'a str, kind: ErrorKind) -> Self {
+        AnubisParseError {
+            input,
+            kind,
+            expected: None,
+        }
+    }
+
+    fn append(_input: &'a str, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
 }
 
-fn block_link(i: &str) -> IResult<&str, BlockContent> {
-    let (not_matched, link) = delimited(char('{'), is_not("{}"), char('}')).parse(i)?;
+impl<'a> ContextError<&'a str> for AnubisParseError<'a> {
+    fn add_context(_input: &'a str, context: &'static str, mut other: Self) -> Self {
+        other.expected.get_or_insert(context);
+        other
+    }
+}
 
-    Ok((not_matched, BlockContent::Link(link.to_string())))
+impl<'a, E> FromExternalError<&'a str, E> for AnubisParseError<'a> {
+    fn from_external_error(input: &'a str, kind: ErrorKind, _error: E) -> Self {
+        Self::from_error_kind(input, kind)
+    }
 }
+/*@*/
 
-fn block_embed(i: &str) -> IResult<&str, BlockContent> {
-    let (not_matched, embed_string) = delimited(tag("{{"), take_until("}}"), tag("}}")).parse(i)?;
+type ParseResult<'a, O> = IResult<&'a str, O, AnubisParseError<'a>>;
 
-    Ok((not_matched, BlockContent::Embed(embed_string.to_string())))
+fn block_name(i: &str) -> ParseResult<'_, &str> {
+    ws(is_not("|]\n")).parse(i)
 }
 
-fn markdown(
-    language_config: &LanguageConfig,
-) -> impl Parser<&str, Output = BlockContent, Error = error::Error<&str>> {
-    many_till(
-        anychar,
-        peek(alt((
-            tag(language_config.anubis_character.as_str()),
-            tag(language_config.multiline_end.as_str()),
-            tag("{"),
+fn template_name(i: &str) -> ParseResult<'_, &str> {
+    ws(is_not("{]\n")).map(str::trim_end).parse(i)
+}
+
+/*@[Template Arguments|Impl]
+# Template Arguments
+Arguments follow the template name as a brace delimited map of `key: value` pairs.
+Keys are bare words or strings, values may be maps, lists, strings, numbers or booleans.
+*/
+fn argument_key(i: &str) -> ParseResult<'_, String> {
+    ws(alt((
+        argument_string,
+        take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-').map(str::to_string),
+    )))
+    .parse(i)
+}
+
+fn argument_string(i: &str) -> ParseResult<'_, String> {
+    delimited(
+        char('"'),
+        cut(opt(escaped_transform(
+            is_not("\"\\"),
+            '\\',
+            alt((
+                value("\\", char('\\')),
+                value("\"", char('"')),
+                value("\n", char('n')),
+                value("\t", char('t')),
+            )),
         ))),
+        context("a closing `\"`", cut(char('"'))),
+    )
+    .map(Option::unwrap_or_default)
+    .parse(i)
+}
+
+fn argument_number(i: &str) -> ParseResult<'_, Value> {
+    recognize_float
+        .map_res(|number: &str| match number.parse::<i64>() {
+            Ok(integer) => Ok(Value::from(integer)),
+            Err(_) => number.parse::<f64>().map(Value::from),
+        })
+        .parse(i)
+}
+
+fn argument_list(i: &str) -> ParseResult<'_, Value> {
+    preceded(
+        char('['),
+        cut(terminated(
+            separated_list0(char(','), argument_value),
+            (
+                opt(ws(char(','))),
+                context("`]` to close the list", char(']')),
+            ),
+        )),
+    )
+    .map(Value::Array)
+    .parse(i)
+}
+
+fn argument_map(i: &str) -> ParseResult<'_, Map<String, Value>> {
+    preceded(
+        char('{'),
+        cut(terminated(
+            separated_list0(
+                char(','),
+                separated_pair(
+                    argument_key,
+                    cut(context("`:` after the argument name", char(':'))),
+                    cut(context("an argument value", argument_value)),
+                ),
+            ),
+            (
+                opt(ws(char(','))),
+                context("`}` to close the map", char('}')),
+            ),
+        )),
+    )
+    .map(|entries| entries.into_iter().collect())
+    .parse(i)
+}
+
+fn argument_value(i: &str) -> ParseResult<'_, Value> {
+    ws(alt((
+        argument_map.map(Value::Object),
+        argument_list,
+        argument_string.map(Value::String),
+        value(Value::Bool(true), tag("true")),
+        value(Value::Bool(false), tag("false")),
+        argument_number,
+    )))
+    .parse(i)
+}
+/*@*/
+
+fn block_header(i: &str) -> ParseResult<'_, (&str, &str, Map<String, Value>)> {
+    delimited(
+        char('['),
+        (
+            context("a block name", block_name),
+            context("`|` after the block name", char('|')),
+            context("a template name", template_name),
+            ws(opt(argument_map)).map(Option::unwrap_or_default),
+        ),
+        context("`]` to close the block header", char(']')),
+    )
+    .map(|(block_name, _, template_name, args)| (block_name, template_name, args))
+    .parse(i)
+}
+
+fn delimited_reference<'a>(
+    start: &'a str,
+    end: &'a str,
+) -> impl Parser<&'a str, Output = &'a str, Error = AnubisParseError<'a>> {
+    delimited(
+        delimiter(start),
+        verify(take_until(end), move |reference: &str| {
+            !reference.is_empty() && !reference.contains(start) && !reference.contains('\n')
+        }),
+        delimiter(end),
     )
-    .map_res(|(matched_chars, _not_matched)| {
-        let matched_string: String = matched_chars.iter().copied().collect();
-        if matched_string.is_empty() {
-            Err(())
+}
+
+fn block_link<'a>(
+    language_config: &'a LanguageConfig,
+) -> impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>> {
+    let delimiters = &language_config.delimiters;
+    delimited_reference(&delimiters.link_start, &delimiters.link_end)
+        .map(|link: &str| BlockContent::Link(BlockLink::parse(link)))
+}
+
+fn block_embed<'a>(
+    language_config: &'a LanguageConfig,
+) -> impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>> {
+    let delimiters = &language_config.delimiters;
+    let mut embed = delimited_reference(&delimiters.embed_start, &delimiters.embed_end);
+    move |input: &'a str| {
+        let (remaining, embed_string) = embed.parse(input)?;
+        let Some(reference) = embed_string.strip_prefix("file:") else {
+            return Ok((remaining, BlockContent::Embed(embed_string.to_string())));
+        };
+        match FileEmbed::parse(reference) {
+            Ok(file) => Ok((remaining, BlockContent::File(file))),
+            Err(_) => Err(nom::Err::Failure(AnubisParseError {
+                input,
+                kind: ErrorKind::Verify,
+                expected: Some(
+                    "a file embed inside the project such as `file:src/db.rs#L17-L40` or `file:src/db.rs#region=save`",
+                ),
+            })),
+        }
+    }
+}
+
+fn delimiter<'a>(
+    delimiter: &'a str,
+) -> impl Parser<&'a str, Output = &'a str, Error = AnubisParseError<'a>> {
+    verify(tag(delimiter), |matched: &str| !matched.is_empty())
+}
+
+/*@[Markdown Text|Impl]
+# Markdown Text
+Collects text until one of the stop sequences. A backslash before the anubis character or
+a link or embed delimiter keeps that sequence as plain text and drops the backslash, any
+other backslash is passed through to the markdown renderer.
+
+Only bytes that can start a stop sequence or an escape are inspected, and the text is
+borrowed from the source unless an escape had to be removed.
+*/
+fn text_until<'a>(
+    stops: Vec<&'a str>,
+    escapable: Vec<&'a str>,
+) -> impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>> {
+    let mut interesting = [false; 256];
+    stops
+        .iter()
+        .chain(&["\\"])
+        .filter_map(|sequence| sequence.as_bytes().first())
+        .for_each(|byte| interesting[*byte as usize] = true);
+
+    move |input: &'a str| {
+        // Only allocated once an escape is found, holding the text up to `copied`
+        let mut unescaped: Option<String> = None;
+        let mut copied = 0;
+        let mut position = 0;
+        let end = loop {
+            let Some(index) = input.as_bytes()[position..]
+                .iter()
+                .position(|byte| interesting[*byte as usize])
+                .map(|found| position + found)
+            else {
+                break input.len();
+            };
+            let remaining = &input[index..];
+            let escaped = remaining.strip_prefix('\\').and_then(|after_backslash| {
+                escapable
+                    .iter()
+                    .find(|sequence| after_backslash.starts_with(**sequence))
+            });
+            if let Some(sequence) = escaped {
+                let text = unescaped.get_or_insert_with(String::new);
+                text.push_str(&input[copied..index]);
+                text.push_str(sequence);
+                position = index + 1 + sequence.len();
+                copied = position;
+                continue;
+            }
+            if stops
+                .iter()
+                .any(|stop| !stop.is_empty() && remaining.starts_with(stop))
+            {
+                break index;
+            }
+            position = index + 1;
+        };
+
+        let text = match unescaped {
+            Some(mut text) => {
+                text.push_str(&input[copied..end]);
+                Cow::Owned(text)
+            }
+            None => Cow::Borrowed(&input[..end]),
+        };
+        if text.is_empty() {
+            Err(nom::Err::Error(AnubisParseError::from_error_kind(
+                input,
+                ErrorKind::TakeUntil,
+            )))
         } else {
-            Ok(BlockContent::Markdown(matched_string))
+            Ok((&input[end..], BlockContent::Markdown(text)))
         }
-    })
+    }
 }
+/*@*/
 
-fn code(
-    language_config: &LanguageConfig,
-) -> impl Parser<&str, Output = BlockContent, Error = error::Error<&str>> {
+fn markdown<'a>(
+    language_config: &'a LanguageConfig,
+    extensions: &'a [Box<dyn ContentExtension>],
+) -> impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>> {
+    let mut stops = vec![
+        language_config.anubis_character.as_str(),
+        language_config.multiline_end.as_str(),
+        language_config.delimiters.link_start.as_str(),
+        language_config.delimiters.embed_start.as_str(),
+    ];
+    stops.extend(extensions.iter().map(|extension| extension.start()));
+    text_until(stops, language_config.escapable_sequences())
+}
+
+// Offers the text between an extension's start and end sequences to each extension in turn
+fn custom_content<'a>(
+    extensions: &'a [Box<dyn ContentExtension>],
+) -> impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>> {
+    move |input: &'a str| {
+        for extension in extensions {
+            let (start, end) = (extension.start(), extension.end());
+            let Some(rest) = input.strip_prefix(start).filter(|_| !start.is_empty()) else {
+                continue;
+            };
+            let Some(text) = rest
+                .find(end)
+                .filter(|_| !end.is_empty())
+                .map(|text_end| &rest[..text_end])
+                .filter(|text| !text.contains('\n'))
+            else {
+                continue;
+            };
+            if let Some(data) = extension.parse(text) {
+                let content = BlockContent::Custom(CustomContent {
+                    extension: extension.name().to_string(),
+                    data,
+                });
+                return Ok((&rest[text.len() + end.len()..], content));
+            }
+        }
+        Err(nom::Err::Error(AnubisParseError::from_error_kind(
+            input,
+            ErrorKind::Tag,
+        )))
+    }
+}
+
+fn code<'a>(
+    language_config: &'a LanguageConfig,
+) -> impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>> {
     delimited(
-        tag(language_config.multiline_end.as_str()),
+        delimiter(language_config.multiline_end.as_str()),
         take_until(language_config.multiline_start.as_str()),
-        tag(language_config.multiline_start.as_str()),
+        delimiter(language_config.multiline_start.as_str()),
     )
-    .map(|code_string: &str| BlockContent::Code(code_string.to_string()))
+    .map(|code_string: &str| BlockContent::Code(Cow::Borrowed(code_string)))
 }
 
-fn block_content(
-    language_config: &LanguageConfig,
-) -> impl Parser<&str, Output = BlockContent, Error = error::Error<&str>> {
+/*@[Markdown Decorations|Impl]
+# Markdown Decorations
+Block comments are often written with a decoration at the start of every line, like the
+` * ` of `/** ... */` comments. When every markdown line between two pieces of code starts
+with one of the language's `decoration_prefixes` it is removed, then the indentation the
+lines share is removed so comrak doesn't render them as lists or code. Single-line comment
+blocks are dedented the same way once their comment prefix is removed. Code segments keep
+their original indentation.
+*/
+fn decoration<'a>(line: &str, decorations: &'a [String]) -> Option<&'a str> {
+    let trimmed = line.trim_start();
+    decorations
+        .iter()
+        .filter(|decoration| !decoration.is_empty())
+        .filter(|decoration| {
+            trimmed
+                .strip_prefix(decoration.as_str())
+                .is_some_and(|rest| rest.chars().next().is_none_or(char::is_whitespace))
+        })
+        .max_by_key(|decoration| decoration.len())
+        .map(String::as_str)
+}
+
+// A line of markdown within a run of segments between two pieces of code
+struct RunLine<'s> {
+    segment: usize,
+    text: &'s str,
+    first: bool,
+    line_start: bool,
+    ends_run: bool,
+}
+
+impl<'s> RunLine<'s> {
+    fn is_blank(text: &str, ends_run: bool) -> bool {
+        text.trim().is_empty() && (ends_run || text.ends_with('\n'))
+    }
+
+    fn line_ending(&self) -> &'s str {
+        &self.text[self.text.len() - self.text.ends_with('\n') as usize..]
+    }
+
+    fn undecorated(&self, decorated: bool, decorations: &[String]) -> &'s str {
+        match decoration(self.text, decorations).filter(|_| decorated) {
+            Some(decoration) => &self.text.trim_start()[decoration.len()..],
+            None => self.text,
+        }
+    }
+
+    fn normalized(&self, decorated: bool, decorations: &[String], indent: usize) -> &'s str {
+        if self.first
+            && decorated
+            && decoration(self.text, decorations).is_some_and(|d| self.text.trim() == d)
+        {
+            return self.line_ending();
+        }
+        if !self.line_start {
+            return self.text;
+        }
+        let text = self.undecorated(decorated, decorations);
+        if Self::is_blank(text, self.ends_run) {
+            self.line_ending()
+        } else {
+            text.get(indent..).unwrap_or(text.trim_start())
+        }
+    }
+}
+
+fn markdown_text<'s>(segment: &'s BlockSegment) -> &'s str {
+    match &segment.content {
+        BlockContent::Markdown(text) => text,
+        _ => "",
+    }
+}
+
+fn run_lines<'s>(run: &'s [BlockSegment], mut at_line_start: bool) -> Vec<RunLine<'s>> {
+    let mut lines = vec![];
+    for (segment, block_segment) in run.iter().enumerate() {
+        let text = markdown_text(block_segment);
+        for (index, line) in text.split_inclusive('\n').enumerate() {
+            lines.push(RunLine {
+                segment,
+                text: line,
+                first: segment == 0 && index == 0,
+                line_start: at_line_start || index > 0,
+                ends_run: false,
+            });
+        }
+        at_line_start = text.ends_with('\n');
+    }
+    if let Some(last_line) = lines
+        .last_mut()
+        .filter(|line| line.segment + 1 == run.len())
+    {
+        last_line.ends_run = true;
+    }
+    lines
+}
+
+fn normalize_run(run: &mut [BlockSegment], decorations: &[String], at_line_start: bool) {
+    let lines = run_lines(run, at_line_start);
+    if !lines.iter().any(|line| line.line_start) {
+        return;
+    }
+    let content_lines = || {
+        lines
+            .iter()
+            .filter(|line| line.line_start && !RunLine::is_blank(line.text, line.ends_run))
+    };
+    let decorated = !decorations.is_empty()
+        && content_lines().all(|line| decoration(line.text, decorations).is_some());
+    let indent = content_lines()
+        .map(|line| (line.undecorated(decorated, decorations), line.ends_run))
+        .filter(|(text, ends_run)| !RunLine::is_blank(text, *ends_run))
+        .map(|(text, _)| text.len() - text.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    // Segments are only copied once one of their lines actually changes
+    let mut rebuilt: Vec<Option<String>> = vec![None; run.len()];
+    for line in lines {
+        let normalized = line.normalized(decorated, decorations, indent);
+        if let Some(text) = &mut rebuilt[line.segment] {
+            text.push_str(normalized);
+        } else if normalized.len() != line.text.len() {
+            let segment_text = markdown_text(&run[line.segment]);
+            let mut text = segment_text[..segment_text.offset(line.text)].to_string();
+            text.push_str(normalized);
+            rebuilt[line.segment] = Some(text);
+        }
+    }
+    for (segment, text) in run.iter_mut().zip(rebuilt) {
+        if let Some(text) = text {
+            segment.content = BlockContent::Markdown(Cow::Owned(text));
+        }
+    }
+}
+
+// The markdown after a piece of code starts on a new line in single-line comment blocks, while in
+// multiline blocks it starts right after the comment is opened again
+fn normalize_markdown(
+    content: &mut Vec<BlockSegment>,
+    decorations: &[String],
+    line_comments: bool,
+) {
+    content
+        .split_mut(|segment| matches!(segment.content, BlockContent::Code(_)))
+        .enumerate()
+        .for_each(|(index, run)| normalize_run(run, decorations, line_comments && index > 0));
+    content.retain(
+        |segment| !matches!(&segment.content, BlockContent::Markdown(text) if text.is_empty()),
+    );
+}
+/*@*/
+
+fn block_content<'a>(
+    language_config: &'a LanguageConfig,
+    extensions: &'a [Box<dyn ContentExtension>],
+) -> impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>> {
     alt((
-        block_link,
-        block_embed,
+        custom_content(extensions),
+        block_embed(language_config),
+        block_link(language_config),
         code(language_config),
-        markdown(language_config),
+        markdown(language_config, extensions),
     ))
 }
 
-fn block(
-    language_config: &LanguageConfig,
-) -> impl Parser<&str, Output = Block, Error = error::Error<&str>> {
-    delimited(
-        ws(tag(language_config.anubis_character.as_str())),
-        pair(block_header, many1(block_content(language_config))),
-        tag(language_config.anubis_character.as_str()),
+/*@[Nested Blocks|Impl]
+# Nested Blocks
+A block may declare child blocks inside its code. The parser returns the parent followed
+by all of its descendants, each child records its parent, and the parent embeds the child
+in place of the child's source.
+*/
+enum BlockItem<'a> {
+    Segment(BlockContent<'a>),
+    Nested((Vec<Block<'a>>, Vec<Diagnostic>)),
+}
+
+fn nested_block<'a>(
+    language_config: &'a LanguageConfig,
+    extensions: &'a [Box<dyn ContentExtension>],
+    source_index: &'a SourceIndex<'a>,
+) -> impl Parser<&'a str, Output = (Vec<Block<'a>>, Vec<Diagnostic>), Error = AnubisParseError<'a>>
+{
+    let anubis_character = language_config.anubis_character.as_str();
+    move |i: &'a str| {
+        // Building the block parser is comparatively costly, so only do it once a header opens
+        peek(pair(ws(tag(anubis_character)), char('['))).parse(i)?;
+        block(language_config, extensions, source_index).parse(i)
+    }
+}
+
+fn adopt_children<'a>(parent: Block<'a>, mut descendants: Vec<Block<'a>>) -> Vec<Block<'a>> {
+    descendants
+        .iter_mut()
+        .filter(|descendant| descendant.info.parent.is_none())
+        .for_each(|child| child.info.parent = Some(parent.info.name.clone()));
+    std::iter::once(parent).chain(descendants).collect()
+}
+
+// A name ending in `+` continues the block with that name
+fn block_info(
+    block_name: &str,
+    template_name: &str,
+    args: Map<String, Value>,
+    location: SourceLocation,
+) -> BlockInfo {
+    let continued = block_name.strip_suffix('+').map(str::trim_end);
+    BlockInfo {
+        name: continued.unwrap_or(block_name).to_string(),
+        template_name: template_name.to_string(),
+        args,
+        parent: None,
+        metadata: Map::new(),
+        location,
+        continuation: continued.is_some(),
+        fragments: vec![],
+        signature: None,
+    }
+}
+
+fn child_segment<'a>(children: &[Block], span: Span) -> BlockSegment<'a> {
+    BlockSegment {
+        content: BlockContent::Embed(children[0].info.name.clone()),
+        span,
+    }
+}
+/*@*/
+
+// Skips the front matter at the start of a block, if it has any
+fn front_matter<'a>(
+    line_text: impl Fn(&'a str) -> Option<&'a str>,
+) -> impl Parser<&'a str, Output = Option<FrontMatter<'a>>, Error = AnubisParseError<'a>> {
+    move |input: &'a str| match split_front_matter(input, &line_text) {
+        Some(front_matter) => Ok((front_matter.rest, Some(front_matter))),
+        None => Ok((input, None)),
+    }
+}
+
+// Front matter that can't be read is reported and the block is kept without metadata
+fn read_front_matter(
+    front_matter: Option<&FrontMatter>,
+    info: &mut BlockInfo,
+    source_index: &SourceIndex,
+) -> Option<Diagnostic> {
+    let front_matter = front_matter?;
+    match front_matter.metadata() {
+        Ok(metadata) => {
+            info.metadata = metadata;
+            None
+        }
+        Err(message) => Some(front_matter_diagnostic(
+            front_matter,
+            &info.name,
+            message,
+            source_index,
+        )),
+    }
+}
+
+fn block<'a>(
+    language_config: &'a LanguageConfig,
+    extensions: &'a [Box<dyn ContentExtension>],
+    source_index: &'a SourceIndex<'a>,
+) -> impl Parser<&'a str, Output = (Vec<Block<'a>>, Vec<Diagnostic>), Error = AnubisParseError<'a>>
+{
+    let anubis_character = language_config.anubis_character.as_str();
+    let multiline_end = language_config.multiline_end.as_str();
+    let decorations = &language_config.decoration_prefixes;
+    let block_line = move |line: &'a str| {
+        let ends_comment = !multiline_end.is_empty() && line.contains(multiline_end);
+        if ends_comment || line.trim_start().starts_with(anubis_character) {
+            return None;
+        }
+        match decoration(line, decorations) {
+            Some(decoration) => Some(&line.trim_start()[decoration.len()..]),
+            None => Some(line),
+        }
+    };
+    // Once the header has been opened we are committed to a block, so later failures are reported
+    consumed(preceded(
+        pair(ws(tag(anubis_character)), peek(char('['))),
+        cut((
+            block_header,
+            front_matter(block_line),
+            context(
+                "block content",
+                many1(consumed(alt((
+                    block_content(language_config, extensions).map(BlockItem::Segment),
+                    nested_block(language_config, extensions, source_index).map(BlockItem::Nested),
+                )))),
+            ),
+            context("a closing `@`", tag(anubis_character)),
+        )),
+    ))
+    .map(
+        move |(matched, ((block_name, template_name, args), front_matter, items, _))| {
+            let mut content = vec![];
+            let mut descendants = vec![];
+            let mut diagnostics = vec![];
+            for (matched, item) in items {
+                let span = source_index.span(matched);
+                match item {
+                    BlockItem::Segment(segment) => content.push(BlockSegment {
+                        content: segment,
+                        span,
+                    }),
+                    BlockItem::Nested((children, child_diagnostics)) => {
+                        content.push(child_segment(&children, span));
+                        descendants.extend(children);
+                        diagnostics.extend(child_diagnostics);
+                    }
+                }
+            }
+            normalize_markdown(&mut content, decorations, false);
+            if front_matter.is_some() {
+                trim_front_matter_line(&mut content);
+            }
+
+            let mut info = block_info(
+                block_name,
+                template_name,
+                args,
+                source_index.location(matched.trim_start()),
+            );
+            diagnostics.extend(read_front_matter(
+                front_matter.as_ref(),
+                &mut info,
+                source_index,
+            ));
+            let parent = Block { info, content };
+            (adopt_children(parent, descendants), diagnostics)
+        },
     )
-    .map(|(header, content)| Block {
-        info: header,
-        content,
-    })
 }
 
-pub fn file_parser(
-    language_config: &LanguageConfig,
-) -> impl Parser<&str, Output = Vec<Block>, Error = error::Error<&str>> {
-    many1(alt((
-        block(language_config).map(Option::Some),
-        value(
-            None,
-            take_until(language_config.anubis_character.as_str()).map_res(
-                |matched_string: &str| {
-                    if matched_string.is_empty() {
-                        Err::<Vec<Block>, ()>(())
+// The markdown starts on the line after the front matter's closing fence
+fn trim_front_matter_line(content: &mut Vec<BlockSegment>) {
+    let Some(BlockContent::Markdown(text)) =
+        content.first_mut().map(|segment| &mut segment.content)
+    else {
+        return;
+    };
+    let line_ending = match text.starts_with("\r\n") {
+        true => 2,
+        false => text.starts_with('\n') as usize,
+    };
+    match text {
+        Cow::Borrowed(borrowed) => *borrowed = &borrowed[line_ending..],
+        Cow::Owned(owned) => {
+            owned.drain(..line_ending);
+        }
+    }
+    if text.trim().is_empty() {
+        content.remove(0);
+    }
+}
+
+/*@[Line Comment Blocks|Impl]
+# Line Comment Blocks
+Blocks written with single line comments, e.g. `#` or `//`. Consecutive prefixed lines
+that aren't indented past the block's opening line become markdown with the prefix
+stripped, any other line becomes code, so comments inside the code stay with it, and blank
+lines belong to whichever kind came before them. A prefixed line starting with the anubis
+character closes the block, unless it opens a nested block.
+*/
+fn line_prefix<'a>(
+    language_config: &'a LanguageConfig,
+    input: &str,
+    block_start: usize,
+) -> Option<&'a str> {
+    let line_start = input[..block_start]
+        .rfind('\n')
+        .map_or(0, |index| index + 1);
+    let before_block = input[line_start..block_start].trim_end();
+    language_config
+        .single_line_prefixes
+        .iter()
+        .filter(|prefix| !prefix.is_empty() && before_block.ends_with(prefix.as_str()))
+        .max_by_key(|prefix| prefix.len())
+        .map(String::as_str)
+}
+
+// How far the line the slice starts on is indented
+fn line_indentation(source: &str, slice: &str) -> usize {
+    let offset = source.offset(slice);
+    let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let before = &source[line_start..offset];
+    before.len() - before.trim_start().len()
+}
+
+fn split_line(i: &str) -> (&str, &str) {
+    let line_end = i.find('\n').map_or(i.len(), |index| index + 1);
+    (&i[line_end..], &i[..line_end])
+}
+
+fn comment_text<'a>(
+    language_config: &'a LanguageConfig,
+    extensions: &'a [Box<dyn ContentExtension>],
+) -> impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>> {
+    let mut stops = vec![
+        language_config.delimiters.link_start.as_str(),
+        language_config.delimiters.embed_start.as_str(),
+    ];
+    stops.extend(extensions.iter().map(|extension| extension.start()));
+    alt((
+        custom_content(extensions),
+        block_embed(language_config),
+        block_link(language_config),
+        text_until(stops, language_config.escapable_sequences()),
+    ))
+}
+
+fn comment_line_content<'a>(
+    line: &'a str,
+    comment_text: &mut impl Parser<&'a str, Output = BlockContent<'a>, Error = AnubisParseError<'a>>,
+    source_index: &'a SourceIndex<'a>,
+) -> Result<Vec<BlockSegment<'a>>, nom::Err<AnubisParseError<'a>>> {
+    let (_, segments) = all_consuming(many0(consumed(|i| comment_text.parse(i))))
+        .parse(line)
+        .map_err(|error| match error {
+            nom::Err::Error(mut error) => {
+                error.expected = Some("a complete link or embed");
+                nom::Err::Failure(error)
+            }
+            error => error,
+        })?;
+
+    Ok(segments
+        .into_iter()
+        .map(|(matched, content)| BlockSegment {
+            content,
+            span: source_index.span(matched),
+        })
+        .collect())
+}
+
+// Adjacent segments of the same kind are merged, and stay borrowed when they are contiguous in the source
+fn push_merged<'a>(
+    segments: &mut Vec<BlockSegment<'a>>,
+    segment: BlockSegment<'a>,
+    source: &'a str,
+) {
+    match (segments.last_mut(), segment.content) {
+        (
+            Some(BlockSegment {
+                content: BlockContent::Markdown(previous),
+                span,
+            }),
+            BlockContent::Markdown(next),
+        )
+        | (
+            Some(BlockSegment {
+                content: BlockContent::Code(previous),
+                span,
+            }),
+            BlockContent::Code(next),
+        ) => {
+            match (&*previous, &next) {
+                (Cow::Borrowed(before), Cow::Borrowed(after))
+                    if source.offset(before) + before.len() == source.offset(after) =>
+                {
+                    let start = source.offset(before);
+                    *previous = Cow::Borrowed(&source[start..start + before.len() + after.len()]);
+                }
+                _ => previous.to_mut().push_str(&next),
+            }
+            *span = span.merge(segment.span);
+        }
+        (_, content) => segments.push(BlockSegment {
+            content,
+            span: segment.span,
+        }),
+    }
+}
+
+fn line_block<'a>(
+    language_config: &'a LanguageConfig,
+    extensions: &'a [Box<dyn ContentExtension>],
+    prefix: &'a str,
+    source_index: &'a SourceIndex<'a>,
+) -> impl Parser<&'a str, Output = (Vec<Block<'a>>, Vec<Diagnostic>), Error = AnubisParseError<'a>>
+{
+    move |input: &'a str| {
+        let anubis_character = language_config.anubis_character.as_str();
+        let (not_matched, (block_name, template_name, args)) = preceded(
+            pair(tag(anubis_character), peek(char('['))),
+            cut(block_header),
+        )
+        .parse(input)?;
+
+        let (mut remaining, header_rest) = split_line(not_matched);
+        let opening_indentation = line_indentation(source_index.source, input);
+        let comment_line = |line: &'a str| {
+            if line.trim().is_empty() {
+                return Some(line);
+            }
+            let indentation = line.len() - line.trim_start().len();
+            let comment = line.trim_start().strip_prefix(prefix)?;
+            let closes = comment.trim_start().starts_with(anubis_character);
+            Some(comment).filter(|_| indentation <= opening_indentation && !closes)
+        };
+        let front_matter =
+            split_front_matter(remaining, comment_line).filter(|_| header_rest.trim().is_empty());
+        if let Some(front_matter) = &front_matter {
+            (remaining, _) = split_line(front_matter.rest);
+        }
+        let mut segments = vec![];
+        let mut descendants = vec![];
+        let mut diagnostics = vec![];
+        let mut previous_was_code = false;
+        let mut comment_text = comment_text(language_config, extensions);
+        comment_line_content(header_rest, &mut comment_text, source_index)?
+            .into_iter()
+            .for_each(|segment| push_merged(&mut segments, segment, source_index.source));
+
+        loop {
+            if remaining.is_empty() {
+                return Err(nom::Err::Failure(AnubisParseError {
+                    input: remaining,
+                    kind: ErrorKind::Eof,
+                    expected: Some("a comment line closing the block with `@`"),
+                }));
+            }
+
+            let (next_line, line) = split_line(remaining);
+            let comment = line.trim_start().strip_prefix(prefix);
+            if let Some(closing) = comment
+                .map(str::trim_start)
+                .filter(|comment| comment.starts_with(anubis_character))
+            {
+                let closing = &remaining[remaining.offset(closing)..];
+                let after_anubis = &closing[anubis_character.len()..];
+                if !after_anubis.starts_with('[') {
+                    remaining = after_anubis;
+                    break;
+                }
+
+                let (not_matched, (children, child_diagnostics)) =
+                    line_block(language_config, extensions, prefix, source_index).parse(closing)?;
+                let child_source = &closing[..closing.offset(not_matched)];
+                segments.push(child_segment(&children, source_index.span(child_source)));
+                descendants.extend(children);
+                diagnostics.extend(child_diagnostics);
+                previous_was_code = true;
+                remaining = not_matched;
+                continue;
+            }
+
+            // Comments indented past the block's opening line belong to its code
+            let indentation = line.len() - line.trim_start().len();
+            match comment.filter(|_| indentation <= opening_indentation) {
+                Some(comment) => {
+                    comment_line_content(comment, &mut comment_text, source_index)?
+                        .into_iter()
+                        .for_each(|segment| {
+                            push_merged(&mut segments, segment, source_index.source)
+                        });
+                    previous_was_code = false;
+                }
+                None => {
+                    let is_code = previous_was_code || !line.trim().is_empty();
+                    let content = if is_code {
+                        BlockContent::Code(Cow::Borrowed(line))
                     } else {
-                        Ok(vec![])
-                    }
-                },
+                        BlockContent::Markdown(Cow::Borrowed(line))
+                    };
+                    let span = source_index.span(line);
+                    push_merged(
+                        &mut segments,
+                        BlockSegment { content, span },
+                        source_index.source,
+                    );
+                    previous_was_code = is_code;
+                }
+            }
+            remaining = next_line;
+        }
+
+        normalize_markdown(&mut segments, &[], true);
+
+        let matched = &input[..input.offset(remaining)];
+        let mut info = block_info(
+            block_name,
+            template_name,
+            args,
+            source_index.location(matched),
+        );
+        diagnostics.extend(read_front_matter(
+            front_matter.as_ref(),
+            &mut info,
+            source_index,
+        ));
+        let parent = Block {
+            info,
+            content: segments,
+        };
+        Ok((
+            remaining,
+            (adopt_children(parent, descendants), diagnostics),
+        ))
+    }
+}
+/*@*/
+
+// With `attach_items` set, a block without code of its own captures the item declared after it
+fn attach_item<'a>(
+    blocks: &mut [Block<'a>],
+    after_block: &'a str,
+    language_config: &LanguageConfig,
+    source_index: &SourceIndex<'a>,
+) {
+    let [block] = blocks else {
+        return;
+    };
+    let has_code = block.content.iter().any(|segment| match &segment.content {
+        BlockContent::Code(code) => !code.trim().is_empty(),
+        _ => false,
+    });
+    if !language_config.attach_items || has_code {
+        return;
+    }
+    let rest = after_block.trim_start_matches([' ', '\t']);
+    let rest = rest
+        .strip_prefix(language_config.multiline_end.as_str())
+        .unwrap_or(rest);
+    let (after_line, rest_of_line) = split_line(rest);
+    if !rest_of_line.trim().is_empty() {
+        return;
+    }
+    if let Some(item) = find_item(after_line, language_config) {
+        block.content.push(BlockSegment {
+            content: BlockContent::Code(Cow::Borrowed(item.code)),
+            span: source_index.span(item.code),
+        });
+        block.info.signature = Some(item.signature);
+    }
+}
+
+fn front_matter_diagnostic(
+    front_matter: &FrontMatter,
+    block_name: &str,
+    message: String,
+    source_index: &SourceIndex,
+) -> Diagnostic {
+    let offset = source_index.source.offset(front_matter.opening_line);
+    let (line, _) = source_index.line_column(offset);
+    let snippet = source_index.line_text(line);
+    Diagnostic::error(
+        format!("invalid front matter in block `{block_name}`: {message}"),
+        source_index.location(snippet),
+        snippet.to_string(),
+    )
+}
+
+/*@[Whole File Blocks|Impl]
+# Whole File Blocks
+With `whole_file` set for a language, e.g. for a folder of hand written markdown guides, each
+file is a single block without any markers. Links, embeds and content extensions are parsed
+in it as usual, except inside fenced code, and a delimiter that doesn't start any of them
+is kept as text. The block is named by the `name` or `title` in its front matter, or else by
+its first heading, falling back to the file's name, and uses the template from the config,
+`Impl` unless set.
+*/
+fn whole_file_block<'a>(
+    language_config: &'a LanguageConfig,
+    whole_file: &'a WholeFile,
+    extensions: &'a [Box<dyn ContentExtension>],
+    source_index: &'a SourceIndex<'a>,
+) -> impl Parser<&'a str, Output = (Vec<Block<'a>>, Vec<Diagnostic>), Error = AnubisParseError<'a>>
+{
+    let mut whole_file_content = whole_file_content(language_config, extensions, source_index);
+    move |input: &'a str| {
+        let front_matter = split_front_matter(input, Some);
+        let content_start = front_matter
+            .as_ref()
+            .map_or(input, |front_matter| split_line(front_matter.rest).0);
+        let content = match whole_file_content.parse(content_start) {
+            Ok((_, content)) => content,
+            Err(nom::Err::Failure(error)) => {
+                let diagnostics = vec![source_index.diagnostic(&error)];
+                return Ok((&input[input.len()..], (vec![], diagnostics)));
+            }
+            Err(error) => return Err(error),
+        };
+        let mut block = Block {
+            info: block_info(
+                "",
+                &whole_file.template,
+                Map::new(),
+                source_index.location(input),
             ),
-        ),
-    )))
-    .map(|result| result.iter().filter_map(|block| block.clone()).collect())
+            content,
+        };
+        // Unreadable front matter leaves the metadata empty, so the name doesn't depend on it
+        let metadata = front_matter.as_ref().map(FrontMatter::metadata);
+        if let Some(Ok(metadata)) = metadata.clone() {
+            block.info.metadata = metadata;
+        }
+        block.info.name = whole_file_name(&block, &source_index.path);
+        let diagnostics = match (&front_matter, metadata) {
+            (Some(front_matter), Some(Err(message))) => vec![front_matter_diagnostic(
+                front_matter,
+                &block.info.name,
+                message,
+                source_index,
+            )],
+            _ => vec![],
+        };
+        Ok((&input[input.len()..], (vec![block], diagnostics)))
+    }
+}
+
+// The segments of markdown without markers, such as a whole file or a notebook cell
+pub(crate) fn whole_file_content<'a>(
+    language_config: &'a LanguageConfig,
+    extensions: &'a [Box<dyn ContentExtension>],
+    source_index: &'a SourceIndex<'a>,
+) -> impl Parser<&'a str, Output = Vec<BlockSegment<'a>>, Error = AnubisParseError<'a>> {
+    let delimiters = &language_config.delimiters;
+    let mut stops = vec![
+        delimiters.link_start.as_str(),
+        delimiters.embed_start.as_str(),
+    ];
+    stops.extend(extensions.iter().map(|extension| extension.start()));
+    // The anubis character has no meaning without markers
+    let mut escapable = language_config.escapable_sequences();
+    escapable.retain(|sequence| *sequence != language_config.anubis_character);
+    let mut segment = consumed(alt((
+        custom_content(extensions),
+        block_embed(language_config),
+        block_link(language_config),
+        text_until(stops, escapable),
+    )));
+
+    move |input: &'a str| {
+        let mut content = vec![];
+        for (region, fenced) in fenced_regions(input) {
+            if fenced {
+                let segment = BlockSegment {
+                    content: BlockContent::Markdown(Cow::Borrowed(region)),
+                    span: source_index.span(region),
+                };
+                push_merged(&mut content, segment, source_index.source);
+                continue;
+            }
+            let mut remaining = region;
+            while !remaining.is_empty() {
+                let (rest, matched, parsed) = match segment.parse(remaining) {
+                    Ok((rest, (matched, parsed))) => (rest, matched, parsed),
+                    Err(nom::Err::Error(_)) => {
+                        let length = remaining.chars().next().map_or(1, char::len_utf8);
+                        let matched = &remaining[..length];
+                        let text = BlockContent::Markdown(Cow::Borrowed(matched));
+                        (&remaining[length..], matched, text)
+                    }
+                    Err(error) => return Err(error),
+                };
+                let segment = BlockSegment {
+                    content: parsed,
+                    span: source_index.span(matched),
+                };
+                push_merged(&mut content, segment, source_index.source);
+                remaining = rest;
+            }
+        }
+        Ok((&input[input.len()..], content))
+    }
+}
+
+pub(crate) fn whole_file_name(block: &Block, path: &Path) -> String {
+    let from_front_matter = ["name", "title"]
+        .iter()
+        .find_map(|key| block.info.metadata.get(*key)?.as_str())
+        .map(str::to_string);
+    from_front_matter
+        .or_else(|| first_heading(block))
+        .or_else(|| Some(path.file_stem()?.to_str()?.to_string()))
+        .unwrap_or_default()
+}
+
+fn code_fence(line: &str) -> Option<&'static str> {
+    ["```", "~~~"]
+        .into_iter()
+        .find(|marker| line.trim_start().starts_with(marker))
+}
+
+// Splits the text into runs of lines outside and inside fenced code, which is left as it is
+fn fenced_regions(text: &str) -> Vec<(&str, bool)> {
+    let mut regions = vec![];
+    let mut region_start = 0;
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        match (fence, code_fence(line)) {
+            (None, Some(marker)) => {
+                regions.push((&text[region_start..offset], false));
+                region_start = offset;
+                fence = Some(marker);
+            }
+            (Some(open), Some(marker)) if open == marker => {
+                regions.push((&text[region_start..offset + line.len()], true));
+                region_start = offset + line.len();
+                fence = None;
+            }
+            _ => {}
+        }
+        offset += line.len();
+    }
+    regions.push((&text[region_start..], fence.is_some()));
+    regions.retain(|(region, _)| !region.is_empty());
+    regions
+}
+
+// The text of the first ATX heading outside of code fences
+fn first_heading(block: &Block) -> Option<String> {
+    let mut fence: Option<&str> = None;
+    for segment in &block.content {
+        let BlockContent::Markdown(markdown) = &segment.content else {
+            continue;
+        };
+        for line in markdown.lines() {
+            let line = line.trim_start();
+            match (fence, code_fence(line)) {
+                (None, Some(marker)) => fence = Some(marker),
+                (Some(open), Some(marker)) if open == marker => fence = None,
+                (None, None) => {
+                    let text = line.trim_start_matches('#');
+                    let level = line.len() - text.len();
+                    let heading = text.trim().trim_end_matches('#').trim_end();
+                    if (1..=6).contains(&level)
+                        && text.starts_with([' ', '\t'])
+                        && !heading.is_empty()
+                    {
+                        return Some(heading.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+/*@*/
+
+pub fn file_parser<'a>(
+    language_config: &'a LanguageConfig,
+    extensions: &'a [Box<dyn ContentExtension>],
+    source_index: &'a SourceIndex<'a>,
+) -> impl Parser<&'a str, Output = (Vec<Block<'a>>, Vec<Diagnostic>), Error = AnubisParseError<'a>>
+{
+    let mut whole_file = language_config
+        .whole_file
+        .as_ref()
+        .map(|whole_file| whole_file_block(language_config, whole_file, extensions, source_index));
+    move |input: &'a str| {
+        if let Some(whole_file) = &mut whole_file {
+            return whole_file.parse(input);
+        }
+        let anubis_character = language_config.anubis_character.as_str();
+        let mut blocks = vec![];
+        let mut diagnostics = vec![];
+        let mut remaining = input;
+        let mut block = block(language_config, extensions, source_index);
+
+        while let Some(block_start) = remaining
+            .find(anubis_character)
+            .filter(|_| !anubis_character.is_empty())
+        {
+            if remaining[..block_start].ends_with('\\') {
+                remaining = &remaining[block_start + anubis_character.len()..];
+                continue;
+            }
+            let block_result = match line_prefix(
+                language_config,
+                input,
+                input.offset(remaining) + block_start,
+            ) {
+                Some(prefix) => line_block(language_config, extensions, prefix, source_index)
+                    .parse(&remaining[block_start..]),
+                None => block.parse(&remaining[block_start..]),
+            };
+            remaining = &remaining[block_start..];
+            match block_result {
+                Ok((not_matched, (mut parsed_blocks, block_diagnostics))) => {
+                    attach_item(
+                        &mut parsed_blocks,
+                        not_matched,
+                        language_config,
+                        source_index,
+                    );
+                    blocks.extend(parsed_blocks);
+                    diagnostics.extend(block_diagnostics);
+                    remaining = not_matched;
+                }
+                Err(nom::Err::Failure(error)) => {
+                    let mut diagnostic = source_index.diagnostic(&error);
+                    let (opened_line, _) = source_index.line_column(input.offset(remaining));
+                    diagnostic.message += &format!(" (block opened on line {opened_line})");
+                    diagnostics.push(diagnostic);
+                    remaining = &remaining[anubis_character.len()..];
+                }
+                Err(_) => remaining = &remaining[anubis_character.len()..],
+            }
+        }
+
+        if let Some(doc_comments) = &language_config.doc_comments {
+            blocks.extend(import_doc_comments(
+                language_config,
+                doc_comments,
+                source_index,
+            ));
+        }
+        Ok((&remaining[remaining.len()..], (blocks, diagnostics)))
+    }
 }
 
 pub fn ws<'a, O, E: ParseError<&'a str>, F>(inner: F) -> impl Parser<&'a str, Output = O, Error = E>
@@ -1,27 +1,42 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, path::Path};
 
 use crate::{
-    common::{Anubis, AnubisError, Block, BlockContent},
+    common::{Anubis, AnubisError, Block, BlockContent, BlockInfo, BlockLink},
     config::LanguageConfig,
     db::HtmlDB,
+    diagnostics::{sort_diagnostics, source_snippet, Diagnostic},
+    encoding::read_text,
+    extension::CustomContent,
+    file_embed::FileEmbed,
 };
-use comrak::{markdown_to_html, ExtensionOptions, Options};
-use tera::Context;
+use comrak::{
+    markdown_to_html, nodes::NodeValue, parse_document, Anchorizer, Arena, ExtensionOptions,
+    Options,
+};
+
+const HEADER_ID_PREFIX: &str = "header";
 
 pub trait AnubisRenderer {
-    fn render(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    fn render(&mut self) -> Result<Vec<Diagnostic>, Box<dyn std::error::Error>>;
     fn render_block(&self, block: &Block) -> Result<(String, String), Box<dyn std::error::Error>>;
     fn render_block_content(
         &self,
         content: &BlockContent,
         lang_config: &LanguageConfig,
+        source: &Path,
     ) -> Result<String, Box<dyn std::error::Error>>;
     fn render_code(code_string: &str, lang_config: &LanguageConfig) -> String;
-    fn render_link(&self, link_string: &str) -> String;
+    fn render_link(&self, link: &BlockLink, source: &Path) -> String;
+    fn render_file(&self, file: &FileEmbed) -> Result<String, Box<dyn std::error::Error>>;
+    fn render_custom(&self, custom: &CustomContent) -> Result<String, Box<dyn std::error::Error>>;
     fn render_markdown(markdown_string: &str) -> String;
+    fn markdown_options() -> Options<'static>;
+    fn heading_anchors(block: &Block) -> Vec<String>;
+    fn check_links(&self) -> Vec<Diagnostic>;
     fn render_embed(
         &self,
         embed_string: &str,
+        source: &Path,
     ) -> Result<(String, String), Box<dyn std::error::Error>>;
     fn render_block_contents(
         &self,
@@ -32,21 +47,23 @@ pub trait AnubisRenderer {
         &self,
         html: &str,
         neighbors: &HashSet<String>,
-        template_name: &str,
+        info: &BlockInfo,
     ) -> Result<String, tera::Error>;
     fn get_language_config(&self, header: &str) -> Result<&LanguageConfig, AnubisError>;
     fn get_neighbors(&self, header: &str) -> Result<&HashSet<String>, AnubisError>;
 }
 
 impl AnubisRenderer for Anubis {
-    fn render(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn render(&mut self) -> Result<Vec<Diagnostic>, Box<dyn std::error::Error>> {
         let blocks = self.database.block_db.values();
         let html_db = blocks
             .map(|block| self.render_block(block))
             .collect::<Result<HtmlDB, Box<dyn std::error::Error>>>();
         self.database.html_db = html_db?;
         self.database.save("./anubis.db")?;
-        Ok(())
+        let mut diagnostics = self.check_links();
+        sort_diagnostics(&mut diagnostics);
+        Ok(diagnostics)
     }
 
     fn render_block(&self, block: &Block) -> Result<(String, String), Box<dyn std::error::Error>> {
@@ -54,8 +71,7 @@ impl AnubisRenderer for Anubis {
         let language_config = self.get_language_config(header)?;
         let html_string = self.render_block_contents(block, language_config)?;
         let neighbors = self.get_neighbors(header)?;
-        let rendered_string =
-            self.apply_template(&html_string, neighbors, &block.info.template_name)?;
+        let rendered_string = self.apply_template(&html_string, neighbors, &block.info)?;
         Ok((header.clone(), rendered_string))
     }
 
@@ -79,13 +95,14 @@ impl AnubisRenderer for Anubis {
         &self,
         html: &str,
         neighbors: &HashSet<String>,
-        template_name: &str,
+        info: &BlockInfo,
     ) -> Result<String, tera::Error> {
-        let mut context = Context::new();
+        let mut context = info.template_context();
         context.insert("html", html);
         context.insert("neighbors", neighbors);
+        context.insert("children", &self.database.get_children(&info.name));
         self.tera
-            .render(&format!("{}.html", template_name), &context)
+            .render(&format!("{}.html", info.template_name), &context)
     }
 
     fn render_block_contents(
@@ -96,7 +113,19 @@ impl AnubisRenderer for Anubis {
         block
             .content
             .iter()
-            .map(|content| self.render_block_content(content, language_config))
+            .enumerate()
+            .map(|(index, segment)| {
+                let location = block.fragment_location(index);
+                self.render_block_content(&segment.content, language_config, &location.path)
+                    .map_err(|error| {
+                        AnubisError::ContextError(format!(
+                            "{}: {}",
+                            location.with_span(segment.span),
+                            error
+                        ))
+                        .into()
+                    })
+            })
             .collect::<Result<String, Box<dyn std::error::Error>>>()
     }
 
@@ -104,55 +133,169 @@ impl AnubisRenderer for Anubis {
         &self,
         content: &BlockContent,
         language_config: &LanguageConfig,
+        source: &Path,
     ) -> Result<String, Box<dyn std::error::Error>> {
         Ok(match content {
             BlockContent::Code(data) => Self::render_code(data, language_config),
             BlockContent::Markdown(data) => Self::render_markdown(data),
-            BlockContent::Link(data) => self.render_link(data),
-            BlockContent::Embed(data) => self.render_embed(data)?.1,
+            BlockContent::Link(link) => self.render_link(link, source),
+            BlockContent::Embed(data) => self.render_embed(data, source)?.1,
+            BlockContent::File(file) => self.render_file(file)?,
+            BlockContent::Custom(custom) => self.render_custom(custom)?,
         })
     }
 
     fn render_code(code_string: &str, lang_config: &LanguageConfig) -> String {
         let language = lang_config.language.clone();
+        let code_string = code_string.trim_matches('\n');
         markdown_to_html(
-            &format!("```{language}{code_string}```"),
+            &format!("```{language}\n{code_string}\n```"),
             &Options::default(),
         )
     }
 
-    fn render_link(&self, link_string: &str) -> String {
+    // Links point at the qualified id of the block they resolve to
+    fn render_link(&self, link: &BlockLink, source: &Path) -> String {
         let url = &self.config.url;
+        let label = link.label.as_ref().unwrap_or(&link.target);
+        let target = self
+            .database
+            .resolve(source, &link.target)
+            .and_then(|header| self.database.get_block(header))
+            .map_or(link.target.clone(), |block| block.info.id());
+        let fragment = link.heading.as_ref().map_or(String::new(), |heading| {
+            format!(
+                "#{HEADER_ID_PREFIX}{}",
+                Anchorizer::new().anchorize(heading.clone())
+            )
+        });
         markdown_to_html(
-            &format!("[{link_string}]({url}{link_string})"),
+            &format!("[{label}]({url}{target}{fragment})"),
             &Options::default(),
         )
     }
 
+    // Highlighted with the language configured for the embedded file, if there is one
+    fn render_file(&self, file: &FileEmbed) -> Result<String, Box<dyn std::error::Error>> {
+        let contents =
+            read_text(&file.path, self.config.fallback_encoding()?).map_err(|error| {
+                AnubisError::ContextError(format!(
+                    "Could not read {}: {error}",
+                    file.path.display()
+                ))
+            })?;
+        let code = file.extract(&contents).map_err(AnubisError::ContextError)?;
+        let lang_config = self
+            .config
+            .get_language_config(&file.path)
+            .cloned()
+            .unwrap_or_default();
+        Ok(Self::render_code(code, &lang_config))
+    }
+
+    fn render_custom(&self, custom: &CustomContent) -> Result<String, Box<dyn std::error::Error>> {
+        let extension = self.get_extension(&custom.extension).ok_or_else(|| {
+            AnubisError::ContextError(format!(
+                "No extension called `{}` is registered",
+                custom.extension
+            ))
+        })?;
+        extension.render(&custom.data, self)
+    }
+
     fn render_markdown(markdown_string: &str) -> String {
-        markdown_to_html(
-            markdown_string,
-            &Options {
-                extension: ExtensionOptions {
-                    header_ids: Some("header".to_string()),
-                    math_dollars: true,
-                    ..Default::default()
-                },
+        markdown_to_html(markdown_string, &Self::markdown_options())
+    }
+
+    fn markdown_options() -> Options<'static> {
+        Options {
+            extension: ExtensionOptions {
+                header_ids: Some(HEADER_ID_PREFIX.to_string()),
+                math_dollars: true,
                 ..Default::default()
             },
-        )
+            ..Default::default()
+        }
+    }
+
+    // The header ids comrak generates for a block, each markdown segment is rendered on its own
+    fn heading_anchors(block: &Block) -> Vec<String> {
+        let options = Self::markdown_options();
+        let mut anchors = vec![];
+        for segment in &block.content {
+            let BlockContent::Markdown(markdown_string) = &segment.content else {
+                continue;
+            };
+            let arena = Arena::new();
+            let root = parse_document(&arena, markdown_string, &options);
+            let mut anchorizer = Anchorizer::new();
+            for heading in root
+                .descendants()
+                .filter(|node| matches!(node.data.borrow().value, NodeValue::Heading(_)))
+            {
+                let text: String = heading
+                    .descendants()
+                    .filter_map(|node| match &node.data.borrow().value {
+                        NodeValue::Text(literal) => Some(literal.to_string()),
+                        NodeValue::Code(code) => Some(code.literal.clone()),
+                        NodeValue::LineBreak | NodeValue::SoftBreak => Some(" ".to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                anchors.push(anchorizer.anchorize(text));
+            }
+        }
+        anchors
+    }
+
+    fn check_links(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for block in self.database.block_db.values() {
+            for (index, segment) in block.content.iter().enumerate() {
+                let BlockContent::Link(link) = &segment.content else {
+                    continue;
+                };
+                let location = block.fragment_location(index).with_span(segment.span);
+                let target = self
+                    .database
+                    .resolve(&location.path, &link.target)
+                    .and_then(|header| self.database.get_block(header));
+                let Some(target) = target else {
+                    let message = format!("link to unknown block `{}`", link.target);
+                    let snippet = source_snippet(&location);
+                    diagnostics.push(Diagnostic::warning(message, location, snippet));
+                    continue;
+                };
+                let Some(heading) = &link.heading else {
+                    continue;
+                };
+                let anchor = Anchorizer::new().anchorize(heading.clone());
+                if !Self::heading_anchors(target).contains(&anchor) {
+                    let message = format!("block `{}` has no heading `{}`", link.target, heading);
+                    let snippet = source_snippet(&location);
+                    diagnostics.push(Diagnostic::error(message, location, snippet));
+                }
+            }
+        }
+        diagnostics
     }
 
     fn render_embed(
         &self,
         embed_string: &str,
+        source: &Path,
     ) -> Result<(String, String), Box<dyn std::error::Error>> {
-        if let Some(block) = self.database.get_block(embed_string) {
+        let block = self
+            .database
+            .resolve(source, embed_string)
+            .and_then(|header| self.database.get_block(header));
+        if let Some(block) = block {
             self.render_block(&block.clone())
         } else {
-            Err(AnubisError::BlockNotFoundError(
-                "Could not find block in database".to_string(),
-            ))?
+            Err(AnubisError::BlockNotFoundError(format!(
+                "Could not find block {:?} in database",
+                embed_string
+            )))?
         }
     }
 }
@@ -33,6 +33,7 @@ impl AnubisServer for Anubis {
     }
 }
 
+#[allow(clippy::unnecessary_unwrap)]
 async fn page_endpoint(
     State(state): State<Arc<Mutex<Anubis>>>,
     extract::Path(page_name): extract::Path<String>,
@@ -40,7 +41,8 @@ async fn page_endpoint(
     let state_access = state.lock(); //obtain access over the AnubisDatabase connection
     if state_access.is_ok() {
         let anubis = state_access.unwrap();
-        if let Some(context) = anubis.database.get_context(&page_name) {
+        let header = anubis.database.resolve_id(&page_name).unwrap_or(&page_name);
+        if let Some(context) = anubis.database.get_context(header) {
             if let Ok(rendered_page) = anubis.tera.render("page.html", &context) {
                 return Html(rendered_page).into_response();
             }
@@ -54,6 +56,7 @@ async fn page_endpoint(
         .into_response()
 }
 
+#[allow(clippy::unnecessary_unwrap)]
 async fn graph(State(state): State<Arc<Mutex<Anubis>>>) -> impl IntoResponse {
     let state_access = state.lock();
     if state_access.is_ok() {
@@ -70,6 +73,7 @@ async fn graph(State(state): State<Arc<Mutex<Anubis>>>) -> impl IntoResponse {
         .into_response()
 }
 
+#[allow(clippy::unnecessary_unwrap)]
 async fn home_page(State(state): State<Arc<Mutex<Anubis>>>) -> impl IntoResponse {
     let state_access = state.lock();
     if state_access.is_ok() {
@@ -0,0 +1,172 @@
+use crate::common::{is_project_path, Anubis, Block, BlockContent};
+use crate::diagnostics::{source_snippet, Diagnostic, Severity};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*@[Tangle|Impl]
+# Tangle
+`anubis tangle` puts the code of blocks back together into files. A block with a `file`
+argument, e.g. `\@[Schema|Impl \{file: "db/schema.sql"}]`, is written to that file, and blocks
+sharing a file follow each other by their `order` argument, then by where they are defined.
+The path is relative to the project root, and absolute paths or paths leaving it through `..`
+are reported instead of written.
+The code of a block is its code segments in order, with the code of the blocks it embeds,
+such as `\{{Users Table}}` or a nested block, in place of the embed, so the prose around them
+is left out. Embeds of unknown blocks and blocks that end up embedding themselves are
+reported, and no file is written until every reference resolves. Files whose contents didn't
+change are left untouched.
+*/
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TangleSummary {
+    pub files: BTreeMap<PathBuf, String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl TangleSummary {
+    pub fn errors(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count()
+    }
+}
+
+impl fmt::Display for TangleSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.errors() {
+            0 => write!(f, "Tangled {} files", self.files.len()),
+            errors => write!(f, "Tangled no files, {errors} error(s) found"),
+        }
+    }
+}
+
+pub trait AnubisTangler {
+    fn tangle(&self) -> Result<TangleSummary, Box<dyn std::error::Error>>;
+    fn tangle_files(&self) -> TangleSummary;
+}
+
+impl AnubisTangler for Anubis {
+    fn tangle(&self) -> Result<TangleSummary, Box<dyn std::error::Error>> {
+        let summary = self.tangle_files();
+        if summary.errors() > 0 {
+            return Ok(summary);
+        }
+        for (path, contents) in &summary.files {
+            if fs::read_to_string(path).is_ok_and(|existing| existing == *contents) {
+                continue;
+            }
+            if let Some(parent) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+        Ok(summary)
+    }
+
+    fn tangle_files(&self) -> TangleSummary {
+        let mut summary = TangleSummary::default();
+        let mut roots: Vec<(&Block, PathBuf, f64)> = vec![];
+        for block in self.database.block_db.values() {
+            let Some(file) = block.info.args.get("file") else {
+                continue;
+            };
+            let file = file.as_str().filter(|file| !file.is_empty());
+            let Some(file) = file.filter(|file| is_project_path(Path::new(file))) else {
+                let location = block.info.location.clone();
+                let message = format!(
+                    "the `file` of block `{}` isn't a path inside the project",
+                    block.info.name
+                );
+                let snippet = source_snippet(&location);
+                summary
+                    .diagnostics
+                    .push(Diagnostic::error(message, location, snippet));
+                continue;
+            };
+            let order = block.info.args.get("order").and_then(Value::as_f64);
+            roots.push((block, PathBuf::from(file), order.unwrap_or(0.0)));
+        }
+        roots.sort_by(|(a, a_file, a_order), (b, b_file, b_order)| {
+            a_file
+                .cmp(b_file)
+                .then(a_order.total_cmp(b_order))
+                .then_with(|| {
+                    let (a, b) = (&a.info.location, &b.info.location);
+                    (&a.path, a.span.start).cmp(&(&b.path, b.span.start))
+                })
+        });
+
+        for (block, file, _) in roots {
+            let mut code = String::new();
+            self.tangle_block(block, &mut vec![], &mut code, &mut summary.diagnostics);
+            if !code.is_empty() && !code.ends_with('\n') {
+                code.push('\n');
+            }
+            summary.files.entry(file).or_default().push_str(&code);
+        }
+        summary
+    }
+}
+/*@*/
+
+impl Anubis {
+    // The blocks being tangled are kept on the stack to catch cycles
+    fn tangle_block<'b>(
+        &'b self,
+        block: &'b Block,
+        stack: &mut Vec<&'b str>,
+        code: &mut String,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        stack.push(&block.info.name);
+        for (index, segment) in block.content.iter().enumerate() {
+            let reference = match &segment.content {
+                BlockContent::Code(text) => {
+                    code.push_str(code_lines(text));
+                    continue;
+                }
+                BlockContent::Embed(reference) => reference,
+                _ => continue,
+            };
+            let location = block.fragment_location(index).with_span(segment.span);
+            let embedded = self
+                .database
+                .resolve(&location.path, reference)
+                .and_then(|name| self.database.get_block(name));
+            let message = match embedded {
+                None => format!(
+                    "block `{}` embeds unknown block `{reference}`",
+                    block.info.name
+                ),
+                Some(embedded) if stack.contains(&embedded.info.name.as_str()) => {
+                    let mut cycle = stack.clone();
+                    cycle.push(&embedded.info.name);
+                    format!("cyclic embed: {}", cycle.join(" -> "))
+                }
+                Some(embedded) => {
+                    self.tangle_block(embedded, stack, code, diagnostics);
+                    continue;
+                }
+            };
+            let snippet = source_snippet(&location);
+            diagnostics.push(Diagnostic::error(message, location, snippet));
+        }
+        stack.pop();
+    }
+}
+
+// The code without the rest of the line a comment was closed on and the indentation of the
+// line the next comment is opened on
+fn code_lines(code: &str) -> &str {
+    let code = match code.split_once('\n') {
+        Some((first_line, rest)) if first_line.trim().is_empty() => rest,
+        _ => code,
+    };
+    code.trim_end_matches([' ', '\t'])
+}
@@ -0,0 +1,171 @@
+use crate::common::{Anubis, Block, BlockContent};
+use crate::db::{AnubisDatabase, TestDB};
+use rayon::{prelude::*, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/*@[Doc Tests|Impl]
+# Doc Tests
+A block with `test: true` in its front matter has its code checked by `anubis test`. The code
+of the block and of the blocks it embeds is written to a temporary file with the extension of
+the file it came from, and the `test_command` of its language is run in that file's
+directory, with `\{file}` replaced by the path of the file and `\{dir}` by the directory.
+The block passes when the command succeeds. Results are kept in the database and shown on
+the block's page until the block is parsed again.
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TestResult {
+    pub passed: bool,
+    pub output: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestSummary {
+    pub results: TestDB,
+}
+
+impl TestSummary {
+    pub fn failed(&self) -> usize {
+        self.results
+            .values()
+            .filter(|result| !result.passed)
+            .count()
+    }
+}
+
+impl fmt::Display for TestSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut results: Vec<_> = self.results.iter().collect();
+        results.sort_by_key(|(name, _)| *name);
+        for (name, result) in results.into_iter().filter(|(_, result)| !result.passed) {
+            writeln!(f, "test `{name}` failed:\n{}", result.output.trim_end())?;
+        }
+        let failed = self.failed();
+        write!(f, "{} passed, {failed} failed", self.results.len() - failed)
+    }
+}
+
+pub trait AnubisTester {
+    fn test(&mut self) -> Result<TestSummary, Box<dyn std::error::Error>>;
+    fn test_blocks(&mut self) -> Result<TestSummary, Box<dyn std::error::Error>>;
+}
+
+impl AnubisTester for Anubis {
+    fn test(&mut self) -> Result<TestSummary, Box<dyn std::error::Error>> {
+        let summary = self.test_blocks()?;
+        self.database.save("./anubis.db")?;
+        Ok(summary)
+    }
+
+    fn test_blocks(&mut self) -> Result<TestSummary, Box<dyn std::error::Error>> {
+        let mut blocks: Vec<&Block> = self
+            .database
+            .block_db
+            .values()
+            .filter(|block| block.info.metadata.get("test") == Some(&Value::Bool(true)))
+            .collect();
+        blocks.sort_by_key(|block| &block.info.name);
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(self.config.jobs.unwrap_or(0))
+            .build()?;
+        let results: TestDB = pool.install(|| {
+            blocks
+                .par_iter()
+                .enumerate()
+                .map(|(index, block)| (block.info.name.clone(), self.test_block(block, index)))
+                .collect()
+        });
+        self.database.test_db = results.clone();
+        Ok(TestSummary { results })
+    }
+}
+/*@*/
+
+impl Anubis {
+    fn test_block(&self, block: &Block, index: usize) -> TestResult {
+        let failure = |output: String| TestResult {
+            passed: false,
+            output,
+        };
+        let Some(command) = self
+            .database
+            .get_lang(&block.info.name)
+            .and_then(|lang_config| lang_config.test_command.as_ref())
+        else {
+            return failure(format!(
+                "no `test_command` is configured for {}",
+                block.info.location.path.display()
+            ));
+        };
+        let directory =
+            std::env::temp_dir().join(format!("anubis-test-{}-{index}", std::process::id()));
+        let extension = block
+            .info
+            .location
+            .path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("txt");
+        let file = directory.join(format!("snippet.{extension}"));
+        let code = block_code(&self.database, block, &mut HashSet::new());
+        let result = fs::create_dir_all(&directory)
+            .and_then(|_| fs::write(&file, code))
+            .and_then(|_| run_test_command(command, &file, &directory));
+        let _ = fs::remove_dir_all(&directory);
+        result.unwrap_or_else(|error| failure(format!("could not run `{command}`: {error}")))
+    }
+}
+
+// The code of a block with the code of the blocks it embeds in place, as it is in the source
+fn block_code(database: &AnubisDatabase, block: &Block, visited: &mut HashSet<String>) -> String {
+    visited.insert(block.info.name.clone());
+    let mut code = String::new();
+    for (index, segment) in block.content.iter().enumerate() {
+        match &segment.content {
+            BlockContent::Code(text) => code.push_str(text),
+            BlockContent::Embed(reference) => {
+                let embedded = database
+                    .resolve(&block.fragment_location(index).path, reference)
+                    .filter(|name| !visited.contains(*name))
+                    .and_then(|name| database.get_block(name));
+                if let Some(embedded) = embedded {
+                    code.push_str(&block_code(database, embedded, visited));
+                }
+            }
+            _ => {}
+        }
+    }
+    code
+}
+
+fn run_test_command(
+    command: &str,
+    file: &Path,
+    directory: &Path,
+) -> Result<TestResult, std::io::Error> {
+    let command = command
+        .replace("{file}", &file.to_string_lossy())
+        .replace("{dir}", &directory.to_string_lossy());
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let output = Command::new(shell)
+        .args([flag, &command])
+        .current_dir(directory)
+        .output()?;
+    Ok(TestResult {
+        passed: output.status.success(),
+        output: format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+    })
+}
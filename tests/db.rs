@@ -1,7 +1,10 @@
 use anubis::{
-    common::{Block, BlockInfo},
+    common::{Block, BlockContent, BlockInfo, BlockLink, BlockSegment, SourceLocation},
+    config::DuplicatePolicy,
     db::*,
 };
+use serde_json::json;
+use std::path::Path;
 
 #[test]
 fn test_block_db() {
@@ -17,6 +20,13 @@ fn test_block_db() {
         info: BlockInfo {
             name: String::new(),
             template_name: String::new(),
+            args: Default::default(),
+            parent: None,
+            metadata: Default::default(),
+            location: SourceLocation::default(),
+            continuation: false,
+            fragments: vec![],
+            signature: None,
         },
         content: vec![],
     };
@@ -28,3 +38,200 @@ fn test_block_db() {
     let block_retrieved = test_db.get_block(test_header);
     assert_eq!(block_retrieved, Some(&block));
 }
+
+fn child_block(name: &str, parent: Option<&str>) -> Block<'static> {
+    Block {
+        info: BlockInfo {
+            name: name.to_string(),
+            template_name: String::new(),
+            args: Default::default(),
+            parent: parent.map(str::to_string),
+            metadata: Default::default(),
+            location: SourceLocation::default(),
+            continuation: false,
+            fragments: vec![],
+            signature: None,
+        },
+        content: vec![],
+    }
+}
+
+#[test]
+fn test_block_tree() {
+    let mut test_db = AnubisDatabase::default();
+    let lang = Default::default();
+    test_db.insert_blocks(
+        vec![
+            child_block("Module", None),
+            child_block("Shape", Some("Module")),
+            child_block("Area", Some("Shape")),
+            child_block("Colour", Some("Module")),
+            child_block("Another", None),
+        ],
+        &lang,
+    );
+
+    let children: Vec<&str> = test_db
+        .get_children("Module")
+        .iter()
+        .map(|info| info.name.as_str())
+        .collect();
+    assert_eq!(children, vec!["Shape", "Colour"]);
+
+    let leaf = |name: &str| BlockTree {
+        name: name.to_string(),
+        id: namespaced_name(Path::new(""), name),
+        children: vec![],
+    };
+    assert_eq!(
+        test_db.get_block_tree(),
+        vec![
+            leaf("Another"),
+            BlockTree {
+                name: "Module".to_string(),
+                id: namespaced_name(Path::new(""), "Module"),
+                children: vec![
+                    BlockTree {
+                        name: "Shape".to_string(),
+                        id: namespaced_name(Path::new(""), "Shape"),
+                        children: vec![leaf("Area")],
+                    },
+                    leaf("Colour"),
+                ],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_metadata_queries() {
+    let mut test_db = AnubisDatabase::default();
+    let tagged = |name: &str, metadata: serde_json::Value| {
+        let mut block = child_block(name, None);
+        block.info.metadata = metadata.as_object().unwrap().clone();
+        block
+    };
+    let blocks = vec![
+        tagged("Parser", json!({"tags": ["core", "parsing"], "weight": 2})),
+        tagged(
+            "Renderer",
+            json!({"tags": "core", "status": "draft", "order": 1}),
+        ),
+        tagged("Server", json!({"status": "stable"})),
+    ];
+    test_db.insert_blocks(blocks, &Default::default());
+
+    let names = |infos: Vec<&BlockInfo>| -> Vec<String> {
+        infos.into_iter().map(|info| info.name.clone()).collect()
+    };
+    assert_eq!(
+        names(test_db.query_metadata("tags", &json!("core"))),
+        vec!["Renderer", "Parser"]
+    );
+    assert_eq!(
+        names(test_db.query_metadata("status", &json!("stable"))),
+        vec!["Server"]
+    );
+    assert!(test_db.query_metadata("owner", &json!("me")).is_empty());
+
+    let tags = test_db.get_tags();
+    assert_eq!(tags["core"], vec!["Parser", "Renderer"]);
+    assert_eq!(tags["parsing"], vec!["Parser"]);
+
+    let roots: Vec<String> = test_db
+        .get_block_tree()
+        .into_iter()
+        .map(|tree| tree.name)
+        .collect();
+    assert_eq!(roots, vec!["Server", "Renderer", "Parser"]);
+}
+
+#[test]
+fn test_qualified_ids() {
+    let mut test_db = AnubisDatabase::default();
+    let located = |name: &str, path: &str, links: &[&str]| {
+        let mut block = child_block(name, None);
+        block.info.location.path = path.into();
+        block.content = links
+            .iter()
+            .map(|link| BlockSegment {
+                content: BlockContent::Link(BlockLink::parse(link)),
+                span: Default::default(),
+            })
+            .collect();
+        block
+    };
+    test_db.insert_blocks(
+        vec![
+            located("Overview", "crates/db/src/lib.rs", &[]),
+            located("Overview", "crates/parser/src/lib.rs", &[]),
+            located("Parser", "crates/parser/src/lib.rs", &["Overview"]),
+            located(
+                "Tokens",
+                "crates/parser/src/tokens.rs",
+                &["Overview", "db::Overview"],
+            ),
+        ],
+        &Default::default(),
+    );
+    test_db.resolve_duplicates(DuplicatePolicy::Namespace);
+    test_db.rebuild_relations();
+
+    let parser_overview = "crates/parser/src/lib.rs::Overview";
+    assert_eq!(
+        test_db.resolve_id("crates/db/src/lib.rs::Overview"),
+        Some("Overview")
+    );
+    assert_eq!(test_db.resolve_id("db::Overview"), Some("Overview"));
+    assert_eq!(
+        test_db.resolve_id("parser::Overview"),
+        Some(parser_overview)
+    );
+    assert_eq!(test_db.resolve_id(parser_overview), Some(parser_overview));
+    assert_eq!(test_db.resolve_id("tokens::Tokens"), Some("Tokens"));
+    assert_eq!(test_db.resolve_id("Missing"), None);
+
+    // References resolve within their own file first, then globally
+    let parser_file = Path::new("crates/parser/src/lib.rs");
+    let tokens_file = Path::new("crates/parser/src/tokens.rs");
+    assert_eq!(
+        test_db.resolve(parser_file, "Overview"),
+        Some(parser_overview)
+    );
+    assert_eq!(test_db.resolve(tokens_file, "Overview"), Some("Overview"));
+    assert!(test_db.graph_db["Parser"].contains(parser_overview));
+    assert!(test_db.graph_db["Tokens"].contains("Overview"));
+
+    let block = test_db.get_block(parser_overview).unwrap();
+    assert_eq!(block.info.local_name(), "Overview");
+    assert_eq!(block.info.id(), parser_overview);
+
+    test_db.remove_block(parser_overview);
+    assert_eq!(test_db.resolve(parser_file, "Overview"), Some("Overview"));
+    assert_eq!(test_db.resolve_id("parser::Overview"), None);
+}
+
+#[test]
+fn test_database_versions() {
+    let directory = std::env::temp_dir().join(format!("anubis-db-version-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("anubis.db");
+
+    AnubisDatabase::default()
+        .save(path.to_str().unwrap())
+        .unwrap();
+    let database = AnubisDatabase::new(Some(path.clone())).unwrap();
+    assert_eq!(database.version, DATABASE_VERSION);
+
+    // Databases from before versioning are rejected rather than misread
+    let unversioned = json!({
+        "block_db": {}, "html_db": {}, "graph_db": {}, "lang_db": {}, "tree_db": {}
+    });
+    std::fs::write(&path, unversioned.to_string()).unwrap();
+    let error = AnubisDatabase::new(Some(path.clone())).unwrap_err();
+    assert!(error
+        .to_string()
+        .ends_with("was saved by another version of anubis"));
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
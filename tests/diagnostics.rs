@@ -0,0 +1,30 @@
+use anubis::{
+    common::{SourceLocation, Span},
+    diagnostics::Diagnostic,
+};
+use std::path::PathBuf;
+
+#[test]
+fn test_diagnostic_display() {
+    let location = SourceLocation {
+        path: PathBuf::from("src/db.rs"),
+        span: Span {
+            start: 20,
+            end: 20,
+            start_line: 17,
+            start_column: 5,
+            end_line: 17,
+            end_column: 5,
+        },
+    };
+    let diagnostic = Diagnostic::error(
+        "expected `|` after the block name, found `]`".to_string(),
+        location,
+        "/*@[Config]".to_string(),
+    );
+
+    assert_eq!(
+        diagnostic.to_string(),
+        "error: expected `|` after the block name, found `]`\n  --> src/db.rs:17:5\n   |\n17 | /*@[Config]\n   |     ^"
+    );
+}
@@ -0,0 +1,154 @@
+use anubis::{
+    common::BlockContent,
+    config::{DocComments, LanguageConfig},
+    doc_import::import_doc_comments,
+    parser_core::SourceIndex,
+};
+use std::path::Path;
+
+fn rust_config() -> LanguageConfig {
+    LanguageConfig {
+        language: "rust".to_string(),
+        multiline_start: "/*".to_string(),
+        multiline_end: "*/".to_string(),
+        single_line_prefixes: vec!["//".to_string()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_import_rust_doc_comments() {
+    let source = "//! Shapes and their areas.\n\n/// A square.\n#[derive(Debug)]\npub struct Shape {\n    side: f64,\n}\n\n/// Measuring shapes.\nimpl<T> Measure for Shape<T> {\n    //// Not a doc comment\n    fn ignored(&self) {}\n\n    /// The area of the shape.\n    ///\n    /// Always positive.\n    fn area(&self) -> f64 {\n        self.side * self.side\n    }\n}\n\nimpl Shape {\n    /// Twice the side.\n    fn double(&self) -> f64 { self.side * 2.0 }\n}\n";
+    let doc_comments = DocComments {
+        line_prefixes: vec!["///".to_string()],
+        module_prefixes: vec!["//!".to_string()],
+        ..Default::default()
+    };
+    let source_index = SourceIndex::new(Path::new("src/shape.rs"), source);
+    let blocks = import_doc_comments(&rust_config(), &doc_comments, &source_index);
+
+    let names: Vec<_> = blocks.iter().map(|block| block.info.local_name()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "shape",
+            "Shape",
+            "<Shape as Measure>",
+            "<Shape as Measure>::area",
+            "Shape::double"
+        ]
+    );
+    assert_eq!(blocks[1].info.name, "src/shape.rs::Shape");
+    assert_eq!(blocks[0].content, vec![blocks[0].content[0].clone()]);
+    assert_eq!(
+        blocks[0].content[0].content,
+        BlockContent::Markdown("Shapes and their areas.\n".into())
+    );
+    assert_eq!(
+        blocks[1].content[1].content,
+        BlockContent::Code("#[derive(Debug)]\npub struct Shape {\n    side: f64,\n}\n".into())
+    );
+    let area = &blocks[3];
+    assert_eq!(
+        area.content[0].content,
+        BlockContent::Markdown("The area of the shape.\n\nAlways positive.\n".into())
+    );
+    assert_eq!(area.content[1].span.start_line, 17);
+    assert_eq!(
+        area.info.parent.as_deref(),
+        Some("src/shape.rs::<Shape as Measure>")
+    );
+    assert_eq!(area.info.template_name, "Impl");
+    assert_eq!(
+        area.info.signature.as_ref().unwrap().returns.as_deref(),
+        Some("f64")
+    );
+    // The undocumented impl doesn't become a parent
+    assert_eq!(blocks[4].info.parent, None);
+}
+
+#[test]
+fn test_import_block_doc_comments_and_docstrings() {
+    let config = LanguageConfig {
+        language: "javascript".to_string(),
+        multiline_start: "/*".to_string(),
+        multiline_end: "*/".to_string(),
+        single_line_prefixes: vec!["//".to_string()],
+        ..Default::default()
+    };
+    let doc_comments = DocComments {
+        block_start: "/**".to_string(),
+        block_end: "*/".to_string(),
+        template: "Api".to_string(),
+        ..Default::default()
+    };
+    let source = "/*\n * Not documentation\n */\n/**\n * Adds two numbers.\n * @param a first\n */\nexport function add(a, b) {\n  return a + b;\n}\n";
+    let source_index = SourceIndex::new(Path::new("src/math.js"), source);
+    let blocks = import_doc_comments(&config, &doc_comments, &source_index);
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].info.name, "src/math.js::add");
+    assert_eq!(blocks[0].info.template_name, "Api");
+    assert_eq!(
+        blocks[0].content[0].content,
+        BlockContent::Markdown("Adds two numbers.\n@param a first\n".into())
+    );
+
+    let config = LanguageConfig {
+        language: "python".to_string(),
+        single_line_prefixes: vec!["#".to_string()],
+        ..Default::default()
+    };
+    let doc_comments = DocComments {
+        docstrings: true,
+        ..Default::default()
+    };
+    let source = "#!/usr/bin/env python\n\"\"\"Geometry helpers.\"\"\"\n\nclass Square:\n    \"\"\"A square.\n\n    Has four sides.\n    \"\"\"\n\n    @property\n    def area(self):\n        '''The area.'''\n        return self.side ** 2\n\n    def side(self):\n        return 1\n";
+    let source_index = SourceIndex::new(Path::new("geometry.py"), source);
+    let blocks = import_doc_comments(&config, &doc_comments, &source_index);
+    let names: Vec<_> = blocks.iter().map(|block| block.info.local_name()).collect();
+    assert_eq!(names, vec!["geometry", "Square", "Square::area"]);
+    assert_eq!(
+        blocks[1].content[0].content,
+        BlockContent::Markdown("A square.\n\nHas four sides.\n".into())
+    );
+    assert_eq!(
+        blocks[2].info.parent.as_deref(),
+        Some("geometry.py::Square")
+    );
+    assert_eq!(
+        blocks[2].content[1].content,
+        BlockContent::Code("    @property\n    def area(self):\n        '''The area.'''\n        return self.side ** 2\n".into())
+    );
+}
+
+#[test]
+fn test_import_names_stay_apart() {
+    let source = "/// Shows the point.\nimpl fmt::Display for Point {\n    /// Writes `(x, y)`.\n    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {\n        // naïve «formatting»\n        write!(f, \"({}, {})\", self.x, self.y)\n    }\n}\n\nimpl fmt::Debug for Point {\n    /// Writes the fields.\n    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {\n        write!(f, \"Point\")\n    }\n}\n";
+    let doc_comments = DocComments {
+        line_prefixes: vec!["///".to_string()],
+        ..Default::default()
+    };
+    let config = LanguageConfig {
+        single_line_prefixes: vec![],
+        ..rust_config()
+    };
+    let source_index = SourceIndex::new(Path::new("src/point.rs"), source);
+    let blocks = import_doc_comments(&config, &doc_comments, &source_index);
+
+    let names: Vec<_> = blocks
+        .iter()
+        .map(|block| block.info.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "src/point.rs::<Point as fmt::Display>",
+            "src/point.rs::<Point as fmt::Display>::fmt",
+            "src/point.rs::<Point as fmt::Debug>::fmt",
+        ]
+    );
+    assert!(matches!(
+        &blocks[1].content[1].content,
+        BlockContent::Code(code) if code.contains("naïve")
+    ));
+}
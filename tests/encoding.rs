@@ -0,0 +1,41 @@
+use anubis::encoding::{decode, fallback_encoding, SourceText, DEFAULT_FALLBACK_ENCODING};
+
+fn text(bytes: &[u8]) -> SourceText {
+    decode(bytes, DEFAULT_FALLBACK_ENCODING)
+}
+
+#[test]
+fn test_decode_encodings() {
+    let expected = SourceText::Text("/*@[Café|Impl]\n*/\n".to_string());
+    assert_eq!(text("/*@[Café|Impl]\n*/\n".as_bytes()), expected);
+    assert_eq!(text("\u{feff}/*@[Café|Impl]\n*/\n".as_bytes()), expected);
+    assert_eq!(text(b"/*@[Caf\xe9|Impl]\n*/\n"), expected);
+
+    let utf16 = |little_endian: bool| -> Vec<u8> {
+        "\u{feff}/*@[Café|Impl]\n*/\n"
+            .encode_utf16()
+            .flat_map(|unit| match little_endian {
+                true => unit.to_le_bytes(),
+                false => unit.to_be_bytes(),
+            })
+            .collect()
+    };
+    assert_eq!(text(&utf16(true)), expected);
+    assert_eq!(text(&utf16(false)), expected);
+
+    let latin2 = fallback_encoding(Some("iso-8859-2")).unwrap();
+    assert_eq!(decode(b"\xb1", latin2), SourceText::Text("ą".to_string()));
+    assert!(fallback_encoding(Some("klingon")).is_err());
+}
+
+#[test]
+fn test_decode_newlines_and_binary() {
+    assert_eq!(
+        text(b"/*@[Block|Impl]\r\n# Title\r\n*/\rcode\n"),
+        SourceText::Text("/*@[Block|Impl]\n# Title\n*/\ncode\n".to_string())
+    );
+    assert_eq!(
+        text(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"),
+        SourceText::Binary
+    );
+}
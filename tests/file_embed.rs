@@ -0,0 +1,68 @@
+use anubis::file_embed::{FileEmbed, FileSelection};
+
+#[test]
+fn test_parse_file_embeds() {
+    let selection = |reference: &str| FileEmbed::parse(reference).map(|file| file.selection);
+    assert_eq!(selection("src/db.rs"), Ok(FileSelection::Whole));
+    assert_eq!(
+        selection("src/db.rs#L17-L40"),
+        Ok(FileSelection::Lines { start: 17, end: 40 })
+    );
+    assert_eq!(
+        selection("src/db.rs#L17"),
+        Ok(FileSelection::Lines { start: 17, end: 17 })
+    );
+    assert_eq!(
+        selection("src/db.rs#region=save"),
+        Ok(FileSelection::Region("save".to_string()))
+    );
+    assert_eq!(
+        FileEmbed::parse(" src/db.rs #L1").unwrap().path.to_str(),
+        Some("src/db.rs")
+    );
+
+    assert!(FileEmbed::parse("#L1").is_err());
+    assert!(FileEmbed::parse("src/db.rs#L40-L17").is_err());
+    assert!(FileEmbed::parse("src/db.rs#L0").is_err());
+    assert!(FileEmbed::parse("src/db.rs#save").is_err());
+    assert!(FileEmbed::parse("src/db.rs#region=").is_err());
+
+    // Files outside the project can't be embedded
+    assert!(FileEmbed::parse("/etc/passwd").is_err());
+    assert!(FileEmbed::parse("../secrets.txt").is_err());
+    assert!(FileEmbed::parse("src/../../secrets.txt#L1").is_err());
+    assert_eq!(
+        FileEmbed::parse("src/../README.md").unwrap().path.to_str(),
+        Some("src/../README.md")
+    );
+}
+
+#[test]
+fn test_extract_file_ranges() {
+    let contents = "fn save() {\n    // #region body\n    let a = 1;\n    // #region inner\n    let b = 2;\n    // #endregion\n    // #endregion\n}\n";
+    let extract = |reference: &str| FileEmbed::parse(reference).unwrap().extract(contents);
+
+    assert_eq!(extract("lib.rs"), Ok(contents));
+    assert_eq!(extract("lib.rs#L1"), Ok("fn save() {\n"));
+    assert_eq!(extract("lib.rs#L7-L8"), Ok("    // #endregion\n}\n"));
+    assert_eq!(
+        extract("lib.rs#region=body"),
+        Ok("    let a = 1;\n    // #region inner\n    let b = 2;\n    // #endregion\n")
+    );
+    assert_eq!(extract("lib.rs#region=inner"), Ok("    let b = 2;\n"));
+
+    assert_eq!(
+        extract("lib.rs#L8-L9"),
+        Err("lines 8-9 are out of range, the file has 8 lines".to_string())
+    );
+    assert_eq!(
+        extract("lib.rs#region=missing"),
+        Err("no region `missing`".to_string())
+    );
+    assert_eq!(
+        FileEmbed::parse("lib.rs#region=open")
+            .unwrap()
+            .extract("// #region open\nlet a = 1;\n"),
+        Err("region `open` is never closed with `#endregion`".to_string())
+    );
+}
@@ -0,0 +1,69 @@
+use anubis::front_matter::{parse_front_matter, split_front_matter};
+use serde_json::json;
+
+#[test]
+fn test_yaml_front_matter() {
+    let text = "\n---\ntags: [core, parsing]\nstatus: draft\nweight: 2\n---\n# Block\nBody\n";
+    let front_matter = split_front_matter(text, Some).unwrap();
+
+    assert_eq!(
+        serde_json::Value::Object(front_matter.metadata().unwrap()),
+        json!({"tags": ["core", "parsing"], "status": "draft", "weight": 2})
+    );
+    assert_eq!(front_matter.opening_line, "---\n");
+    assert_eq!(front_matter.rest, "\n# Block\nBody\n");
+}
+
+#[test]
+fn test_toml_front_matter() {
+    let text = "+++\nowner = \"docs\"\naliases = [\"Old Name\"]\n+++";
+    let front_matter = split_front_matter(text, Some).unwrap();
+
+    assert_eq!(
+        serde_json::Value::Object(front_matter.metadata().unwrap()),
+        json!({"owner": "docs", "aliases": ["Old Name"]})
+    );
+    assert_eq!(front_matter.rest, "");
+
+    assert_eq!(
+        parse_front_matter("---", "owner = \"docs\"\n").unwrap(),
+        json!({"owner": "docs"}).as_object().unwrap().clone()
+    );
+}
+
+#[test]
+fn test_without_front_matter() {
+    assert!(split_front_matter("# Block\n---\nnot: metadata\n---\n", Some).is_none());
+    assert!(split_front_matter("---\nnot: closed\n", Some).is_none());
+}
+
+#[test]
+fn test_front_matter_line_text() {
+    let text = "\n * ---\n *   owner: alice@example.com\n *   team: {name: core}\n * ---\n */";
+    let front_matter = split_front_matter(text, |line| {
+        let line = line.trim_start();
+        (!line.starts_with("*/")).then(|| line.trim_start_matches('*'))
+    })
+    .unwrap();
+
+    assert_eq!(
+        serde_json::Value::Object(front_matter.metadata().unwrap()),
+        json!({"owner": "alice@example.com", "team": {"name": "core"}})
+    );
+    assert_eq!(front_matter.rest, "\n */");
+
+    let unclosed = split_front_matter("---\nowner: docs\n*/\n---\n", |line| {
+        (!line.contains("*/")).then_some(line)
+    });
+    assert!(unclosed.is_none());
+}
+
+#[test]
+fn test_invalid_front_matter() {
+    let front_matter = split_front_matter("\n---\n- just\n- a list\n---\n", Some).unwrap();
+
+    assert_eq!(
+        front_matter.metadata().unwrap_err(),
+        "front matter must be a map of keys to values"
+    );
+}
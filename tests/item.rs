@@ -0,0 +1,133 @@
+use anubis::{
+    config::LanguageConfig,
+    item::{find_item, ItemSignature},
+};
+
+fn rust_config() -> LanguageConfig {
+    LanguageConfig {
+        language: "rust".to_string(),
+        multiline_start: "/*".to_string(),
+        multiline_end: "*/".to_string(),
+        single_line_prefixes: vec!["//".to_string()],
+        ..Default::default()
+    }
+}
+
+fn python_config() -> LanguageConfig {
+    LanguageConfig {
+        language: "python".to_string(),
+        single_line_prefixes: vec!["#".to_string()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_find_rust_items() {
+    let source = "\n#[inline]\npub(crate) fn area<'a, T: Into<f64>>(shape: &'a Shape<T>, scale: impl Fn(f64) -> f64) -> Result<f64, String>\nwhere\n    T: Copy,\n{\n    let close = '}';\n    // a } in a comment\n    Ok(\"{\".len() as f64)\n}\n\nfn unrelated() {}\n";
+    let item = find_item(source, &rust_config()).unwrap();
+    assert!(item.code.starts_with("#[inline]\n"));
+    assert!(item.code.ends_with("as f64)\n}\n"));
+    assert_eq!(
+        item.signature,
+        ItemSignature {
+            kind: "fn".to_string(),
+            name: "area".to_string(),
+            params: vec![
+                "shape: &'a Shape<T>".to_string(),
+                "scale: impl Fn(f64) -> f64".to_string()
+            ],
+            returns: Some("Result<f64, String>".to_string()),
+            text: "pub(crate) fn area<'a, T: Into<f64>>(shape: &'a Shape<T>, scale: impl Fn(f64) -> f64) -> Result<f64, String>\nwhere\n    T: Copy,".to_string(),
+        }
+    );
+
+    let item = find_item(
+        "impl<T> Display for Shape<T> where T: Copy {\n}\nstruct Unit;\n",
+        &rust_config(),
+    )
+    .unwrap();
+    assert_eq!(item.signature.name, "Display for Shape<T>");
+    assert_eq!(
+        item.code,
+        "impl<T> Display for Shape<T> where T: Copy {\n}\n"
+    );
+
+    let item = find_item("pub struct Unit;\nfn next() {}\n", &rust_config()).unwrap();
+    assert_eq!(
+        (item.code, item.signature.kind.as_str()),
+        ("pub struct Unit;\n", "struct")
+    );
+
+    assert_eq!(find_item("let x = fn_call();\n", &rust_config()), None);
+    assert_eq!(find_item("fn unclosed() {\n", &rust_config()), None);
+}
+
+#[test]
+fn test_find_python_items() {
+    let source = "    @property\n    def area(self, scale: float = 1.0) -> float:\n        text = \"):\"\n\n        return self.width * scale\n\n    def perimeter(self): pass\n";
+    let item = find_item(source, &python_config()).unwrap();
+    assert_eq!(
+        item.code,
+        "    @property\n    def area(self, scale: float = 1.0) -> float:\n        text = \"):\"\n\n        return self.width * scale\n"
+    );
+    assert_eq!(item.signature.name, "area");
+    assert_eq!(item.signature.params, vec!["self", "scale: float = 1.0"]);
+    assert_eq!(item.signature.returns.as_deref(), Some("float"));
+
+    let item = find_item("class Shape(Base):\n    pass\nx = 1\n", &python_config()).unwrap();
+    assert_eq!(item.code, "class Shape(Base):\n    pass\n");
+    assert_eq!(
+        (item.signature.name.as_str(), item.signature.params.len()),
+        ("Shape", 0)
+    );
+
+    let item = find_item(
+        "def one(): return 1\ndef two(): return 2\n",
+        &python_config(),
+    )
+    .unwrap();
+    assert_eq!(item.code, "def one(): return 1\n");
+}
+
+#[test]
+fn test_find_items_with_non_ascii_text() {
+    // Only the block comment delimiters are set, so every character is checked for a comment
+    let config = LanguageConfig {
+        single_line_prefixes: vec![],
+        ..rust_config()
+    };
+    let source = "fn café() -> &'static str {\n    // naïve «»\n    \"crème\"\n}\n";
+    let item = find_item(source, &config).unwrap();
+    assert_eq!(item.code, source);
+    assert_eq!(item.signature.name, "café");
+
+    let source = "def größe(x):\n    return 'ü' * x\n";
+    let item = find_item(source, &python_config()).unwrap();
+    assert_eq!(item.code, source);
+    assert_eq!(item.signature.name, "größe");
+}
+
+#[test]
+fn test_find_items_with_tricky_literals() {
+    // Braces in raw strings, char literals and after lifetimes don't end the item
+    let source = "fn braces<'a>(text: &'a str) -> &'a str {\n    let raw = r#\"} \" }\"#;\n    let path = r\"C:\\\";\n    let open = '{';\n    text\n}\nfn next() {}\n";
+    let item = find_item(source, &rust_config()).unwrap();
+    assert_eq!(item.code, &source[..source.find("fn next").unwrap()]);
+    assert_eq!(item.signature.params, vec!["text: &'a str"]);
+
+    // A signature spread over several lines
+    let source = "pub fn long(\n    first: u32,\n    second: (u32, u32),\n) -> u32 {\n    first + second.0\n}\n";
+    let item = find_item(source, &rust_config()).unwrap();
+    assert_eq!(item.code, source);
+    assert_eq!(
+        item.signature.params,
+        vec!["first: u32", "second: (u32, u32)"]
+    );
+    assert_eq!(item.signature.returns.as_deref(), Some("u32"));
+
+    // Lines of a multiline string don't end an indented item, whatever their indentation
+    let source = "def usage(\n    name,\n    width=80,\n):\n    text = '''\nUsage: {name}\n'''\n    return text[:width]\nx = 1\n";
+    let item = find_item(source, &python_config()).unwrap();
+    assert_eq!(item.code, &source[..source.find("x = 1").unwrap()]);
+    assert_eq!(item.signature.params, vec!["name", "width=80"]);
+}
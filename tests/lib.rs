@@ -2,7 +2,16 @@ pub mod cli;
 pub mod common;
 pub mod config;
 pub mod db;
+pub mod diagnostics;
+pub mod doc_import;
+pub mod encoding;
+pub mod file_embed;
+pub mod front_matter;
+pub mod item;
+pub mod notebook;
 pub mod parser;
 pub mod parser_core;
 pub mod renderer;
 pub mod server;
+pub mod tangler;
+pub mod tester;
@@ -0,0 +1,145 @@
+use anubis::{
+    common::{BlockContent, BlockLink},
+    config::{AnubisConfig, LanguageConfig, NotebookConfig, WholeFile},
+    notebook::{CellKind, Notebook},
+    parser_core::SourceIndex,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+const NOTEBOOK: &str = r##"{
+  "cells": [
+    {
+      "cell_type": "markdown",
+      "metadata": {},
+      "source": ["# Cleaning Data\n", "Counted by {Count}.\n", "<!--@[Loading|Impl]\n", "Reads the csv.\n", "@-->"]
+    },
+    {
+      "cell_type": "code",
+      "execution_count": 1,
+      "metadata": {},
+      "outputs": [
+        {"output_type": "stream", "name": "stdout", "text": ["3 rows\n"]},
+        {"output_type": "execute_result", "execution_count": 1, "metadata": {}, "data": {"text/plain": "42", "image/png": "..."}},
+        {"output_type": "display_data", "metadata": {}, "data": {"image/png": "..."}}
+      ],
+      "source": "#@[Count|Impl]\n# Counts the rows\nrows = len(data)\n#@"
+    },
+    {"cell_type": "raw", "metadata": {}, "source": "#@[Ignored|Impl]\n#@"}
+  ],
+  "metadata": {"language_info": {"name": "python", "file_extension": ".py"}},
+  "nbformat": 4,
+  "nbformat_minor": 5
+}"##;
+
+fn config() -> AnubisConfig {
+    let markdown = LanguageConfig {
+        language: "markdown".to_string(),
+        anubis_character: "@".to_string(),
+        multiline_start: "<!--".to_string(),
+        multiline_end: "-->".to_string(),
+        // Cells are never whole files of their own
+        whole_file: Some(WholeFile::default()),
+        ..Default::default()
+    };
+    let python = LanguageConfig {
+        language: "python".to_string(),
+        anubis_character: "@".to_string(),
+        single_line_prefixes: vec!["#".to_string()],
+        ..Default::default()
+    };
+    AnubisConfig {
+        language_configs: HashMap::from([("md".to_string(), markdown), ("py".to_string(), python)]),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_read_notebook() {
+    let notebook = Notebook::parse(NOTEBOOK).unwrap();
+    assert_eq!(notebook.code_extension, "py");
+    let kinds: Vec<_> = notebook.cells.iter().map(|cell| &cell.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![&CellKind::Markdown, &CellKind::Code, &CellKind::Raw]
+    );
+    assert_eq!(
+        &notebook.text[notebook.cells[1].range.clone()],
+        "#@[Count|Impl]\n# Counts the rows\nrows = len(data)\n#@"
+    );
+    assert_eq!(notebook.cells[1].outputs, vec!["3 rows\n", "42"]);
+    assert!(Notebook::parse("{\"cells\": 1}").is_err());
+}
+
+#[test]
+fn test_notebook_blocks() {
+    let notebook = Notebook::parse(NOTEBOOK).unwrap();
+    let source_index = SourceIndex::new(Path::new("analysis/cleaning.ipynb"), &notebook.text);
+    let mut notebook_config = LanguageConfig {
+        language: "python".to_string(),
+        anubis_character: "@".to_string(),
+        notebook: Some(NotebookConfig::default()),
+        ..Default::default()
+    };
+
+    let (blocks, diagnostics) = notebook
+        .blocks(&config(), &notebook_config, &[], &source_index)
+        .unwrap();
+    assert!(diagnostics.is_empty());
+    let names: Vec<_> = blocks
+        .iter()
+        .map(|block| block.info.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["Cleaning Data", "Loading", "Count"]);
+    assert_eq!(blocks[0].info.template_name, "Impl");
+
+    // Only the blocks declared by markers
+    notebook_config.notebook = Some(NotebookConfig {
+        markers_only: true,
+        ..Default::default()
+    });
+    let (blocks, _) = notebook
+        .blocks(&config(), &notebook_config, &[], &source_index)
+        .unwrap();
+    let names: Vec<_> = blocks
+        .iter()
+        .map(|block| block.info.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["Loading", "Count"]);
+    assert_eq!(blocks[1].info.location.span.start_line, 6);
+    assert_eq!(
+        blocks[1].content.last().unwrap().content,
+        BlockContent::Code("rows = len(data)\n".into())
+    );
+
+    notebook_config.whole_file = Some(WholeFile {
+        template: "Notebook".to_string(),
+    });
+    notebook_config.notebook = Some(NotebookConfig {
+        outputs: true,
+        ..Default::default()
+    });
+    let (blocks, _) = notebook
+        .blocks(&config(), &notebook_config, &[], &source_index)
+        .unwrap();
+    let page = &blocks[0];
+    assert_eq!(page.info.name, "Cleaning Data");
+    assert_eq!(page.info.template_name, "Notebook");
+    let content: Vec<_> = page
+        .content
+        .iter()
+        .map(|segment| &segment.content)
+        .collect();
+    assert_eq!(
+        content,
+        vec![
+            &BlockContent::Markdown("# Cleaning Data\nCounted by ".into()),
+            &BlockContent::Link(BlockLink::parse("Count")),
+            &BlockContent::Markdown(".\n<!--@[Loading|Impl]\nReads the csv.\n@-->".into()),
+            &BlockContent::Code("#@[Count|Impl]\n# Counts the rows\nrows = len(data)\n#@".into()),
+            &BlockContent::Code("3 rows\n".into()),
+            &BlockContent::Code("42".into()),
+        ]
+    );
+    assert_eq!(page.content[3].span.start_line, 6);
+}
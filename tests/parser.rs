@@ -1 +1,564 @@
+use anubis::{
+    common::{Anubis, BlockContent},
+    config::{AnubisConfig, DuplicatePolicy, LanguageConfig, UnconfiguredPolicy},
+    db::{namespaced_name, AnubisDatabase},
+    diagnostics::Severity,
+    parser::AnubisParser,
+};
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tera::Tera;
 
+fn test_anubis() -> Anubis {
+    let language_config = LanguageConfig {
+        language: "rust".to_string(),
+        anubis_character: "@".to_string(),
+        multiline_start: "/*".to_string(),
+        multiline_end: "*/".to_string(),
+        ..Default::default()
+    };
+    Anubis {
+        config: AnubisConfig {
+            language_configs: [("rs".to_string(), language_config)].into(),
+            ..Default::default()
+        },
+        database: AnubisDatabase::default(),
+        tera: Tera::default(),
+        extensions: vec![],
+    }
+}
+
+fn write_source(path: &Path, contents: &str, modified: SystemTime) {
+    fs::write(path, contents).unwrap();
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+fn block_names(anubis: &Anubis) -> Vec<&str> {
+    let mut names: Vec<&str> = anubis
+        .database
+        .block_db
+        .keys()
+        .map(String::as_str)
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_incremental_parsing() {
+    let directory = std::env::temp_dir().join(format!("anubis-incremental-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let first = directory.join("first.rs");
+    let second = directory.join("second.rs");
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    write_source(
+        &first,
+        "/*@[First|Impl]\nSee {Second}\n*/\n/*@*/\n/*@[Broken|Impl]\n",
+        start,
+    );
+    write_source(&second, "/*@[Second|Impl]\n*/\n/*@*/\n", start);
+    let files = |paths: &[&PathBuf]| -> HashSet<PathBuf> {
+        paths.iter().map(|path| path.to_path_buf()).collect()
+    };
+
+    let mut anubis = test_anubis();
+    let summary = anubis.parse_files(files(&[&first, &second])).unwrap();
+    assert_eq!((summary.parsed_files, summary.unchanged_files), (2, 0));
+
+    let summary = anubis.parse_files(files(&[&first, &second])).unwrap();
+    assert_eq!((summary.parsed_files, summary.unchanged_files), (0, 2));
+    // Diagnostics of unchanged files are still reported
+    assert_eq!(summary.diagnostics.len(), 1);
+
+    // Touching a file without changing it only needs the hash to be checked
+    write_source(
+        &second,
+        "/*@[Second|Impl]\n*/\n/*@*/\n",
+        start + Duration::from_secs(1),
+    );
+    let summary = anubis.parse_files(files(&[&first, &second])).unwrap();
+    assert_eq!((summary.parsed_files, summary.unchanged_files), (0, 2));
+
+    write_source(
+        &second,
+        "/*@[Renamed|Impl]\n*/\n/*@*/\n/*@[Second|Impl]\n*/\n/*@*/\n",
+        start + Duration::from_secs(2),
+    );
+    let summary = anubis.parse_files(files(&[&first, &second])).unwrap();
+    assert_eq!((summary.parsed_files, summary.unchanged_files), (1, 1));
+    assert_eq!(block_names(&anubis), vec!["First", "Renamed", "Second"]);
+    assert!(anubis.database.graph_db["Second"].contains("First"));
+
+    let summary = anubis.parse_files(files(&[&first])).unwrap();
+    assert_eq!(
+        (
+            summary.parsed_files,
+            summary.unchanged_files,
+            summary.removed_files
+        ),
+        (0, 1, 1)
+    );
+    assert_eq!(block_names(&anubis), vec!["First"]);
+    assert_eq!(
+        summary.to_string(),
+        "Parsed 0 of 1 files (1 unchanged, 1 removed)"
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_parallel_parsing_is_deterministic() {
+    let directory = std::env::temp_dir().join(format!("anubis-parallel-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let files: HashSet<PathBuf> = (0..16)
+        .map(|index| {
+            let path = directory.join(format!("file{index}.rs"));
+            let next = (index + 1) % 16;
+            write_source(
+                &path,
+                &format!("/*@[Block{index}|Impl]\nSee {{Block{next}}}\n*/\n/*@*/\n/*@[Broken{index}|Impl]\n"),
+                start,
+            );
+            path
+        })
+        .collect();
+
+    let parse_with = |jobs| {
+        let mut anubis = test_anubis();
+        anubis.config.jobs = Some(jobs);
+        let summary = anubis.parse_files(files.clone()).unwrap();
+        (anubis, summary)
+    };
+    let (sequential, sequential_summary) = parse_with(1);
+    let (parallel, parallel_summary) = parse_with(4);
+
+    assert_eq!(parallel_summary.parsed_files, 16);
+    assert_eq!(sequential_summary.diagnostics, parallel_summary.diagnostics);
+    assert_eq!(sequential.database.block_db, parallel.database.block_db);
+    assert_eq!(sequential.database.graph_db, parallel.database.graph_db);
+    assert_eq!(
+        serde_json::to_value(sequential.database.get_block_tree()).unwrap(),
+        serde_json::to_value(parallel.database.get_block_tree()).unwrap()
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_duplicate_blocks() {
+    let directory = std::env::temp_dir().join(format!("anubis-duplicates-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let first = directory.join("a.rs");
+    let second = directory.join("b.rs");
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    write_source(&first, "/*@[Config|Impl]\nFirst\n*/\n/*@*/\n", start);
+    write_source(
+        &second,
+        "/*@[Other|Impl]\n*/\n/*@*/\n/*@[Config|Impl]\nSecond\n*/\n/*@*/\n/*@[Config|Impl]\nThird\n*/\n/*@*/\n",
+        start,
+    );
+    let files: HashSet<PathBuf> = [first.clone(), second.clone()].into();
+    let namespaced = namespaced_name(&second, "Config");
+
+    // Blocks sharing a name across files are kept under their qualified ids, a name declared
+    // twice in the same file only keeps its first declaration, and both are reported
+    let mut anubis = test_anubis();
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    let messages: Vec<_> = summary
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.severity,
+                diagnostic.location.span.start_line,
+                diagnostic.message.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (
+                Severity::Error,
+                4,
+                format!(
+                    "duplicate block `Config`, first defined at {}:1:3, kept as `{namespaced}`",
+                    first.display()
+                )
+            ),
+            (
+                Severity::Error,
+                8,
+                format!(
+                    "duplicate block `Config`, first defined at {}:4:3",
+                    second.display()
+                )
+            ),
+        ]
+    );
+    assert!(summary
+        .diagnostics
+        .iter()
+        .all(|diagnostic| diagnostic.location.path == second));
+    assert_eq!(anubis.database.block_db["Config"].info.location.path, first);
+    assert_eq!(
+        block_names(&anubis),
+        vec![namespaced.as_str(), "Config", "Other"]
+    );
+    assert_eq!(anubis.database.block_db[&namespaced].info.name, namespaced);
+    assert_eq!(
+        anubis.database.block_db[&namespaced]
+            .info
+            .location
+            .span
+            .start_line,
+        4
+    );
+
+    assert_eq!(summary.duplicate_errors, 2);
+
+    // Unchanged files still report their duplicates
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert_eq!(summary.unchanged_files, 2);
+    assert_eq!(summary.diagnostics.len(), 2);
+    assert_eq!(summary.duplicate_errors, 2);
+
+    anubis.config.duplicate_blocks = DuplicatePolicy::Warn;
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert_eq!(summary.diagnostics.len(), 2);
+    assert!(summary
+        .diagnostics
+        .iter()
+        .all(|diagnostic| diagnostic.severity == Severity::Warning));
+    assert_eq!(summary.duplicate_errors, 0);
+
+    // With the namespace policy names repeated across files are expected, so only the
+    // references that can't tell them apart are reported
+    let third = directory.join("c.rs");
+    write_source(&third, "/*@[Usage|Impl]\nSee {Config}\n*/\n/*@*/\n", start);
+    let files: HashSet<PathBuf> = [first.clone(), second.clone(), third.clone()].into();
+    anubis.config.duplicate_blocks = DuplicatePolicy::Namespace;
+    let summary = anubis.parse_files(files).unwrap();
+    assert_eq!(summary.diagnostics.len(), 2);
+    assert_eq!(summary.diagnostics[0].location.path, second);
+    assert_eq!(summary.diagnostics[0].location.span.start_line, 8);
+    let ambiguous = &summary.diagnostics[1];
+    assert_eq!(ambiguous.severity, Severity::Warning);
+    assert_eq!(ambiguous.location.path, third);
+    assert_eq!(ambiguous.location.span.start_line, 2);
+    assert_eq!(
+        ambiguous.message,
+        format!(
+            "`Config` is declared in several files ({0}:1:3, {1}:4:3), it refers to the first one, use a qualified id such as `{0}::Config` instead",
+            first.display(),
+            second.display()
+        )
+    );
+    assert_eq!(summary.duplicate_errors, 0);
+
+    // Removing the first declaration hands the name over to the next one
+    write_source(
+        &second,
+        "/*@[Other|Impl]\n*/\n/*@*/\n/*@[Config|Impl]\nSecond\n*/\n/*@*/\n",
+        start + Duration::from_secs(1),
+    );
+    let summary = anubis.parse_files([second.clone()].into()).unwrap();
+    assert!(summary.diagnostics.is_empty());
+    assert_eq!(block_names(&anubis), vec!["Config", "Other"]);
+    assert_eq!(
+        anubis.database.block_db["Config"].info.location.path,
+        second
+    );
+    assert!(anubis.database.duplicate_db.is_empty());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_block_continuations() {
+    let directory =
+        std::env::temp_dir().join(format!("anubis-continuations-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let first = directory.join("a.rs");
+    let second = directory.join("b.rs");
+    let third = directory.join("c.rs");
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    write_source(&first, "/*@[Shape|Impl]\nThe trait\n*/\n/*@*/\n", start);
+    write_source(
+        &second,
+        "/*@[Shape+|Impl]\nSecond\n*/\n/*@*/\n/*@[Missing +|Impl]\nNothing\n*/\n/*@*/\n",
+        start,
+    );
+    write_source(
+        &third,
+        "/*@[Shape+|Impl]\n---\nweight: -1\n---\nFirst\n*/\n/*@*/\n",
+        start,
+    );
+    let files: HashSet<PathBuf> = [first.clone(), second.clone(), third.clone()].into();
+    let markdown = |anubis: &Anubis| -> Vec<String> {
+        anubis.database.block_db["Shape"]
+            .content
+            .iter()
+            .filter_map(|segment| match &segment.content {
+                BlockContent::Markdown(markdown) => Some(markdown.to_string()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let mut anubis = test_anubis();
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert_eq!(block_names(&anubis), vec!["Shape"]);
+    assert_eq!(summary.diagnostics.len(), 1);
+    assert_eq!(
+        summary.diagnostics[0].message,
+        "continuation of unknown block `Missing`"
+    );
+    assert_eq!(
+        markdown(&anubis),
+        vec!["\nThe trait\n", "First\n", "\nSecond\n"]
+    );
+    let block = &anubis.database.block_db["Shape"];
+    let fragments: Vec<(&Path, usize)> = block
+        .info
+        .fragments
+        .iter()
+        .map(|fragment| (fragment.location.path.as_path(), fragment.segments))
+        .collect();
+    assert_eq!(
+        fragments,
+        vec![
+            (first.as_path(), 2),
+            (third.as_path(), 2),
+            (second.as_path(), 2)
+        ]
+    );
+    assert_eq!(block.fragment_location(1).path, first);
+    assert_eq!(block.fragment_location(4).path, second);
+
+    // Changing one fragment merges the block again from its stored parts
+    write_source(
+        &second,
+        "/*@[Shape+|Impl]\nChanged\n*/\n/*@*/\n",
+        start + Duration::from_secs(1),
+    );
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert_eq!(summary.parsed_files, 1);
+    assert!(summary.diagnostics.is_empty());
+    assert_eq!(
+        markdown(&anubis),
+        vec!["\nThe trait\n", "First\n", "\nChanged\n"]
+    );
+
+    anubis
+        .parse_files([first.clone(), second.clone()].into())
+        .unwrap();
+    assert_eq!(markdown(&anubis), vec!["\nThe trait\n", "\nChanged\n"]);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_embedded_file_dependencies() {
+    let directory = std::env::temp_dir().join(format!("anubis-embeds-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let source = directory.join("lib.rs");
+    // Embedded files have to be inside the project, which tests run from
+    let embedded = PathBuf::from(format!("target/anubis-embeds-{}.txt", std::process::id()));
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    fs::write(&embedded, "first\nsecond\n").unwrap();
+    write_source(
+        &source,
+        &format!(
+            "/*@[Data|Impl]\n{{{{file:{0}#L2}}}}\n*/\n/*@*/\n/*@[Region|Impl]\n{{{{file:{0}#region=missing}}}}\n*/\n/*@*/\n",
+            embedded.display()
+        ),
+        start,
+    );
+    let files: HashSet<PathBuf> = [source.clone()].into();
+
+    let mut anubis = test_anubis();
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert_eq!(summary.diagnostics.len(), 1);
+    assert_eq!(
+        summary.diagnostics[0].message,
+        format!("embedded file `{}` no region `missing`", embedded.display())
+    );
+    assert_eq!(summary.diagnostics[0].location.span.start_line, 6);
+    assert_eq!(
+        anubis
+            .database
+            .get_file(&source)
+            .unwrap()
+            .dependencies
+            .len(),
+        1
+    );
+
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert_eq!(summary.unchanged_files, 1);
+
+    // Changing an embedded file parses the files embedding it again
+    fs::write(
+        &embedded,
+        "first\nchanged\n// #region missing\n// #endregion\n",
+    )
+    .unwrap();
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert_eq!(summary.parsed_files, 1);
+    assert!(summary.diagnostics.is_empty());
+
+    fs::remove_file(&embedded).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_encodings_and_binary_files() {
+    let directory = std::env::temp_dir().join(format!("anubis-encodings-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let crlf = directory.join("crlf.rs");
+    let latin1 = directory.join("latin1.rs");
+    let binary = directory.join("binary.rs");
+    fs::write(
+        &crlf,
+        "/*@[Windows|Impl]\r\n# Windows\r\n*/\r\nfn main() {}\r\n/*@*/\r\n",
+    )
+    .unwrap();
+    fs::write(&latin1, b"/*@[Caf\xe9|Impl]\n*/\n/*@*/\n").unwrap();
+    fs::write(&binary, b"\x7fELF\x02\x01\x01\x00\x00\x00").unwrap();
+    let files: HashSet<PathBuf> = [crlf, latin1, binary].into();
+
+    let mut anubis = test_anubis();
+    let summary = anubis.parse_files(files).unwrap();
+    assert!(summary.diagnostics.is_empty());
+    assert_eq!((summary.parsed_files, summary.skipped_files), (2, 1));
+    assert_eq!(
+        summary.to_string(),
+        "Parsed 2 of 2 files (0 unchanged, 0 removed), skipped 1 binary files"
+    );
+    assert_eq!(block_names(&anubis), vec!["Café", "Windows"]);
+    let windows = &anubis.database.block_db["Windows"].content;
+    assert_eq!(
+        windows[0].content,
+        BlockContent::Markdown("\n# Windows\n".into())
+    );
+    assert_eq!(
+        windows[1].content,
+        BlockContent::Code("\nfn main() {}\n".into())
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_unconfigured_files() {
+    let directory =
+        std::env::temp_dir().join(format!("anubis-unconfigured-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let source = directory.join("lib.rs");
+    fs::write(&source, "/*@[Lib|Impl]\n*/\n/*@*/\n").unwrap();
+    let unconfigured = ["ci.yml", "deploy.yml", "Makefile"].map(|name| directory.join(name));
+    unconfigured
+        .iter()
+        .for_each(|file| fs::write(file, "").unwrap());
+    let files: HashSet<PathBuf> = unconfigured.iter().chain([&source]).cloned().collect();
+
+    let mut anubis = test_anubis();
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert_eq!(summary.parsed_files, 1);
+    assert_eq!(
+        summary.unconfigured_files,
+        [
+            (
+                ".yml".to_string(),
+                vec![unconfigured[0].clone(), unconfigured[1].clone()]
+            ),
+            ("no extension".to_string(), vec![unconfigured[2].clone()]),
+        ]
+        .into()
+    );
+    assert_eq!(
+        summary.to_string(),
+        format!(
+            "Parsed 1 of 1 files (0 unchanged, 0 removed)\nSkipped 3 files without a language config:\n  .yml (2): {}, {}\n  no extension (1): {}",
+            unconfigured[0].display(),
+            unconfigured[1].display(),
+            unconfigured[2].display()
+        )
+    );
+
+    anubis.config.unconfigured_files = UnconfiguredPolicy::Skip;
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert!(summary.unconfigured_files.is_empty());
+    assert_eq!(summary.unchanged_files, 1);
+
+    anubis.config.unconfigured_files = UnconfiguredPolicy::Error;
+    assert!(anubis.parse_files(files).is_err());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_notebooks() {
+    let directory = std::env::temp_dir().join(format!("anubis-notebooks-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let notebook = directory.join("analysis.ipynb");
+    fs::write(
+        &notebook,
+        r#"{"cells": [{"cell_type": "code", "outputs": [], "source": ["/*@[Query|Impl]\n", "*/\n", "let rows = 3;\n", "/*@*/\n"]}], "metadata": {"language_info": {"file_extension": ".rs"}}}"#,
+    )
+    .unwrap();
+    let broken = directory.join("broken.ipynb");
+    fs::write(&broken, "{\"cells\": [").unwrap();
+
+    let mut anubis = test_anubis();
+    let notebook_config = LanguageConfig {
+        language: "rust".to_string(),
+        anubis_character: "@".to_string(),
+        notebook: Some(Default::default()),
+        ..Default::default()
+    };
+    anubis
+        .config
+        .language_configs
+        .insert("ipynb".to_string(), notebook_config);
+    assert!(anubis.parse_file(&notebook).unwrap());
+    // The notebook is a block of its own even without `whole_file`
+    assert_eq!(block_names(&anubis), vec!["Query", "analysis"]);
+    let block = anubis.database.get_block("Query").unwrap();
+    assert_eq!(
+        block.content.last().unwrap().content,
+        BlockContent::Code("\nlet rows = 3;\n".into())
+    );
+
+    // A malformed notebook is reported without stopping the other files from being parsed
+    let summary = anubis
+        .parse_files([notebook.clone(), broken.clone()].into())
+        .unwrap();
+    assert!(anubis.database.get_block("Query").is_some());
+    assert_eq!(summary.diagnostics.len(), 1);
+    assert_eq!(summary.diagnostics[0].severity, Severity::Error);
+    assert!(summary.diagnostics[0]
+        .message
+        .starts_with("could not read notebook"));
+    assert_eq!(summary.diagnostics[0].snippet, "{\"cells\": [");
+
+    // Notebooks are parsed again when the config of their cells' language changes
+    assert!(!anubis.parse_file(&notebook).unwrap());
+    let rust_config = anubis.config.language_configs.get_mut("rs").unwrap();
+    rust_config.decoration_prefixes = vec!["*".to_string()];
+    assert!(anubis.parse_file(&notebook).unwrap());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
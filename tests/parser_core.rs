@@ -1 +1,679 @@
+use anubis::{
+    common::{BlockContent, BlockLink, Span},
+    config::{Delimiters, LanguageConfig, WholeFile},
+    extension::{ContentExtension, CustomContent},
+    file_embed::{FileEmbed, FileSelection},
+    parser_core::{file_parser, SourceIndex},
+};
+use nom::Parser;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
 
+fn rust_config() -> LanguageConfig {
+    LanguageConfig {
+        language: "rust".to_string(),
+        anubis_character: "@".to_string(),
+        multiline_start: "/*".to_string(),
+        multiline_end: "*/".to_string(),
+        single_line_prefixes: vec!["//".to_string()],
+        ..Default::default()
+    }
+}
+
+fn python_config() -> LanguageConfig {
+    LanguageConfig {
+        language: "python".to_string(),
+        anubis_character: "@".to_string(),
+        single_line_prefixes: vec!["#".to_string()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_block_locations() {
+    let source = "fn main() {}\n/*@[Example|Impl]\n# Example\n*/\nfn example() {}\n/*@*/\n";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("src/example.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    assert_eq!(blocks.len(), 1);
+    let block = &blocks[0];
+    assert_eq!(block.info.name, "Example");
+    assert_eq!(block.info.location.path, PathBuf::from("src/example.rs"));
+    assert_eq!(
+        block.info.location.span,
+        Span {
+            start: 15,
+            end: 63,
+            start_line: 2,
+            start_column: 3,
+            end_line: 6,
+            end_column: 4,
+        }
+    );
+    assert_eq!(block.info.location.to_string(), "src/example.rs:2:3");
+
+    let code = &block.content[1];
+    assert_eq!(
+        code.content,
+        BlockContent::Code("\nfn example() {}\n".into())
+    );
+    assert_eq!(code.span.start_line, 4);
+    assert_eq!(code.span.end_line, 6);
+    assert_eq!(
+        &source[code.span.start..code.span.end],
+        "*/\nfn example() {}\n/*"
+    );
+}
+
+#[test]
+fn test_line_column_counts_characters() {
+    let source_index = SourceIndex::new(Path::new("a.md"), "é\nab");
+    assert_eq!(source_index.line_column(0), (1, 1));
+    assert_eq!(source_index.line_column(2), (1, 2));
+    assert_eq!(source_index.line_column(4), (2, 2));
+}
+
+#[test]
+fn test_bad_block_does_not_hide_others() {
+    let source = "/*@[Broken|Impl\n# Broken\n@*/\n/*@[Unclosed|Impl]\n# Unclosed\n*/\n/*@[Valid|Impl]\n# Valid\n@*/\n";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("src/broken.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    let names: Vec<&str> = blocks
+        .iter()
+        .map(|block| block.info.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["Valid"]);
+
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(
+        diagnostics[0].message,
+        "expected `]` to close the block header, found `#` (block opened on line 1)"
+    );
+    assert_eq!(diagnostics[0].location.span.start_line, 2);
+    assert_eq!(diagnostics[0].snippet, "# Broken");
+    assert_eq!(
+        diagnostics[1].message,
+        "expected a closing `@`, found `*` (block opened on line 4)"
+    );
+    assert_eq!(diagnostics[1].location.span.start_line, 9);
+}
+
+#[test]
+fn test_template_arguments() {
+    let source = "/*@[Factorial Function|FunctionTemplate{args: {n: \"u32\"}, return: \"u32\", tags: [\"math\", 1, 2.5, true],}]\n# Factorial\n@*/";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("src/math.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    assert_eq!(blocks[0].info.template_name, "FunctionTemplate");
+    assert_eq!(
+        serde_json::Value::Object(blocks[0].info.args.clone()),
+        serde_json::json!({
+            "args": {"n": "u32"},
+            "return": "u32",
+            "tags": ["math", 1, 2.5, true],
+        })
+    );
+}
+
+#[test]
+fn test_template_argument_errors() {
+    let source = "/*@[Broken|Template{n \"u32\"}]\n# Broken\n@*/";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("src/math.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(blocks.is_empty());
+    assert_eq!(
+        diagnostics[0].message,
+        "expected `:` after the argument name, found `\"` (block opened on line 1)"
+    );
+    assert_eq!(diagnostics[0].location.span.start_column, 23);
+}
+
+#[test]
+fn test_file_embeds() {
+    let source = "/*@[Save|Impl]\nSee {{file:src/db.rs#L17-L40}}\n*/\n/*@*/\n/*@[Broken|Impl]\n{{file:src/db.rs#save}}\n*/\n/*@*/\n";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("src/lib.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(
+        blocks[0].content[1].content,
+        BlockContent::File(FileEmbed {
+            path: "src/db.rs".into(),
+            selection: FileSelection::Lines { start: 17, end: 40 },
+        })
+    );
+    assert_eq!(
+        diagnostics[0].message,
+        "expected a file embed inside the project such as `file:src/db.rs#L17-L40` or `file:src/db.rs#region=save`, found `{` (block opened on line 5)"
+    );
+}
+
+struct IssueExtension;
+
+impl ContentExtension for IssueExtension {
+    fn name(&self) -> &str {
+        "issue"
+    }
+
+    fn start(&self) -> &str {
+        "{!"
+    }
+
+    fn parse(&self, text: &str) -> Option<serde_json::Value> {
+        let number = text.strip_prefix("issue ")?.trim().parse::<u64>().ok()?;
+        Some(serde_json::json!({ "number": number }))
+    }
+
+    fn render(
+        &self,
+        data: &serde_json::Value,
+        _anubis: &anubis::common::Anubis,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(format!("<a href=\"/issues/{0}\">#{0}</a>", data["number"]))
+    }
+}
+
+#[test]
+fn test_content_extensions() {
+    let source = "/*@[Issues|Impl]\nFixes {!issue 12} but not {!pr 3}\n*/\n/*@*/\n";
+    let config = rust_config();
+    let extensions: Vec<Box<dyn ContentExtension>> = vec![Box::new(IssueExtension)];
+    let source_index = SourceIndex::new(Path::new("src/lib.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &extensions, &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    assert_eq!(
+        blocks[0].content[1].content,
+        BlockContent::Custom(CustomContent {
+            extension: "issue".to_string(),
+            data: serde_json::json!({ "number": 12 }),
+        })
+    );
+    assert_eq!(
+        blocks[0].content[3].content,
+        BlockContent::Link(BlockLink::parse("!pr 3"))
+    );
+}
+
+#[test]
+fn test_attached_items() {
+    let source = "/*@[Area|Impl]\n# Area\n@*/\nfn area(width: f64) -> f64 {\n    width * width\n}\n\nfn unrelated() {}\n/*@[Kept|Impl]\n*/\nfn kept() {}\n/*@*/\n";
+    let config = LanguageConfig {
+        attach_items: true,
+        ..rust_config()
+    };
+    let source_index = SourceIndex::new(Path::new("src/lib.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    assert_eq!(
+        blocks[0].content[1].content,
+        BlockContent::Code("fn area(width: f64) -> f64 {\n    width * width\n}\n".into())
+    );
+    assert_eq!(blocks[0].content[1].span.start_line, 4);
+    let signature = blocks[0].info.signature.as_ref().unwrap();
+    assert_eq!(
+        (
+            signature.name.as_str(),
+            signature.params.clone(),
+            signature.returns.as_deref()
+        ),
+        ("area", vec!["width: f64".to_string()], Some("f64"))
+    );
+    // Blocks with code of their own are left as they are
+    assert_eq!(blocks[1].info.signature, None);
+
+    let source = "class Shape:\n    #@[Perimeter|Impl]\n    # Perimeter\n    #@\n    def perimeter(self):\n        return 4 * self.side\n\n    def area(self): pass\n";
+    let config = LanguageConfig {
+        attach_items: true,
+        ..python_config()
+    };
+    let source_index = SourceIndex::new(Path::new("shape.py"), source);
+    let (_, (blocks, _)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+    assert_eq!(
+        blocks[0].content.last().unwrap().content,
+        BlockContent::Code("    def perimeter(self):\n        return 4 * self.side\n".into())
+    );
+}
+
+#[test]
+fn test_single_line_comment_blocks() {
+    let source = "import os\n#@[Greeting|Impl]\n# # Greeting\n# Says hello, see {Farewell}\n#   - in English\n\ndef greet():\n    print(\"hi\")\n\n# More prose\nx = 1\n# @\nprint(greet())\n";
+    let config = python_config();
+    let source_index = SourceIndex::new(Path::new("greet.py"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    assert_eq!(blocks.len(), 1);
+    let content: Vec<&BlockContent> = blocks[0]
+        .content
+        .iter()
+        .map(|segment| &segment.content)
+        .collect();
+    assert_eq!(
+        content,
+        vec![
+            &BlockContent::Markdown("\n# Greeting\nSays hello, see ".into()),
+            &BlockContent::Link(BlockLink::parse("Farewell")),
+            &BlockContent::Markdown("\n  - in English\n\n".into()),
+            &BlockContent::Code("def greet():\n    print(\"hi\")\n\n".into()),
+            &BlockContent::Markdown("More prose\n".into()),
+            &BlockContent::Code("x = 1\n".into()),
+        ]
+    );
+    assert_eq!(blocks[0].content[3].span.start_line, 7);
+    assert_eq!(blocks[0].content[3].span.end_line, 10);
+    assert_eq!(blocks[0].info.location.span.end_line, 12);
+}
+
+#[test]
+fn test_indented_comments_stay_in_code() {
+    let source = "class Greeter:\n    #@[Greet|Impl]\n    # Greets by name\n    def greet(self, name):\n        # say hi to {name}\n        print(name)\n    #@\n";
+    let config = python_config();
+    let source_index = SourceIndex::new(Path::new("greet.py"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    let content: Vec<&BlockContent> = blocks[0]
+        .content
+        .iter()
+        .map(|segment| &segment.content)
+        .collect();
+    assert_eq!(
+        content,
+        vec![
+            &BlockContent::Markdown("\nGreets by name\n".into()),
+            &BlockContent::Code(
+                "    def greet(self, name):\n        # say hi to {name}\n        print(name)\n"
+                    .into()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_mixed_comment_styles() {
+    let source = "/*@[Multi|Impl]\n# Multi\n*/\nfn multi() {}\n/*@*/\n// @[Single|Impl]\n// # Single\nfn single() {}\n//@\n/*@[After|Impl]\n# After\n@*/\n";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("src/mixed.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    let names: Vec<&str> = blocks
+        .iter()
+        .map(|block| block.info.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["Multi", "Single", "After"]);
+    assert_eq!(
+        blocks[1].content[1].content,
+        BlockContent::Code("fn single() {}\n".into())
+    );
+}
+
+#[test]
+fn test_unclosed_single_line_block() {
+    let source = "#@[Open|Impl]\n# text\ncode()\n";
+    let config = python_config();
+    let source_index = SourceIndex::new(Path::new("open.py"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(blocks.is_empty());
+    assert_eq!(
+        diagnostics[0].message,
+        "expected a comment line closing the block with `@`, found end of file (block opened on line 1)"
+    );
+}
+
+#[test]
+fn test_nested_blocks() {
+    let source = "/*@[Module|Impl]\n# Module\n*/\nmod shapes {\n    /*@[Shape|Impl]\n    # Shape\n    */\n    struct Shape;\n    /*@[Area|Impl]\n    # Area\n    */\n    fn area() {}\n    /*@*/\n    /*@*/\n}\n/*@*/\n";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("src/shapes.rs"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    let family: Vec<(&str, Option<&str>)> = blocks
+        .iter()
+        .map(|block| (block.info.name.as_str(), block.info.parent.as_deref()))
+        .collect();
+    assert_eq!(
+        family,
+        vec![
+            ("Module", None),
+            ("Shape", Some("Module")),
+            ("Area", Some("Shape")),
+        ]
+    );
+    assert_eq!(
+        blocks[0].content[2].content,
+        BlockContent::Embed("Shape".to_string())
+    );
+    assert_eq!(blocks[0].content[2].span.start_line, 5);
+    assert_eq!(
+        blocks[0].content[3].content,
+        BlockContent::Code("\n}\n".into())
+    );
+}
+
+#[test]
+fn test_nested_single_line_blocks() {
+    let source = "#@[Shapes|Impl]\n# Shapes\nclass Shape:\n    #@[Area|Impl]\n    # Area\n    def area(self): pass\n    #@\n#@\n";
+    let config = python_config();
+    let source_index = SourceIndex::new(Path::new("shapes.py"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[1].info.parent.as_deref(), Some("Shapes"));
+    assert_eq!(
+        blocks[0].content[2].content,
+        BlockContent::Embed("Area".to_string())
+    );
+}
+
+fn block_contents(source: &str, config: &LanguageConfig) -> Vec<BlockContent<'static>> {
+    let source_index = SourceIndex::new(Path::new("test"), source);
+    let (_, (blocks, diagnostics)) = file_parser(config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+    assert!(diagnostics.is_empty());
+    blocks[0]
+        .content
+        .iter()
+        .map(|segment| segment.content.clone().into_owned())
+        .collect()
+}
+
+#[test]
+fn test_escaped_delimiters() {
+    let source = "/*@[Escapes|Impl]\nstruct \\{ x: u32 \\} mail me\\@home {Target} \\*kept\\*\n@*/";
+    assert_eq!(
+        block_contents(source, &rust_config()),
+        vec![
+            BlockContent::Markdown("\nstruct { x: u32 } mail me@home ".into()),
+            BlockContent::Link(BlockLink::parse("Target")),
+            BlockContent::Markdown(" \\*kept\\*\n".into()),
+        ]
+    );
+
+    let escaped_block = "\\@[NotABlock|Impl] text @";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("test"), escaped_block);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(escaped_block)
+        .unwrap();
+    assert!(blocks.is_empty());
+    assert!(diagnostics.is_empty());
+}
+
+#[test]
+fn test_configured_delimiters() {
+    let config = LanguageConfig {
+        language: "handlebars".to_string(),
+        anubis_character: "@".to_string(),
+        multiline_start: "{{!--".to_string(),
+        multiline_end: "--}}".to_string(),
+        delimiters: Delimiters {
+            link_start: "[[".to_string(),
+            link_end: "]]".to_string(),
+            embed_start: "![[".to_string(),
+            embed_end: "]]".to_string(),
+        },
+        ..Default::default()
+    };
+    let source = "{{!--@[Card|Impl]\nSee [[Layout]] and ![[Footer]], {not a link}\n--}}\n<p>{{ title }}</p>\n{{!--@--}}";
+    assert_eq!(
+        block_contents(source, &config),
+        vec![
+            BlockContent::Markdown("\nSee ".into()),
+            BlockContent::Link(BlockLink::parse("Layout")),
+            BlockContent::Markdown(" and ".into()),
+            BlockContent::Embed("Footer".to_string()),
+            BlockContent::Markdown(", {not a link}\n".into()),
+            BlockContent::Code("\n<p>{{ title }}</p>\n".into()),
+        ]
+    );
+}
+
+#[test]
+fn test_link_labels_and_headings() {
+    let source = "/*@[Links|Impl]\n{Target} {Target#Some Heading} {Target|custom label} {Target # Usage | see usage}\n@*/";
+    let links: Vec<BlockLink> = block_contents(source, &rust_config())
+        .into_iter()
+        .filter_map(|content| match content {
+            BlockContent::Link(link) => Some(link),
+            _ => None,
+        })
+        .collect();
+
+    let link = |target: &str, heading: Option<&str>, label: Option<&str>| BlockLink {
+        target: target.to_string(),
+        heading: heading.map(str::to_string),
+        label: label.map(str::to_string),
+    };
+    assert_eq!(
+        links,
+        vec![
+            link("Target", None, None),
+            link("Target", Some("Some Heading"), None),
+            link("Target", None, Some("custom label")),
+            link("Target", Some("Usage"), Some("see usage")),
+        ]
+    );
+}
+
+#[test]
+fn test_front_matter_diagnostics() {
+    let source = "/*@[Tagged|Impl]\n---\ntags: [core]\n---\n# Tagged\n*/\n/*@*/\n\
+                  /*@[Broken|Impl]\n---\ntags: [core\n---\n*/\n/*@*/\n";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("test"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].info.metadata["tags"], serde_json::json!(["core"]));
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0]
+        .message
+        .starts_with("invalid front matter in block `Broken`"));
+    assert_eq!(diagnostics[0].location.span.start_line, 9);
+    assert_eq!(diagnostics[0].snippet, "---");
+}
+
+#[test]
+fn test_front_matter_read_before_content() {
+    let source = "/*@[Owned|Impl]\n---\nowner: alice@example.com\nteam: {name: core}\n---\n# Owned\n*/\nfn owned() {}\n/*@*/\n\
+                  //@[Lines|Impl]\n// +++\n// owner = { name = \"bob@example.com\" }\n// +++\n// Prose\nlet a = 1;\n//@\n";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("test"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    assert!(diagnostics.is_empty());
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(
+        serde_json::Value::Object(blocks[0].info.metadata.clone()),
+        serde_json::json!({"owner": "alice@example.com", "team": {"name": "core"}})
+    );
+    assert_eq!(
+        blocks[0]
+            .content
+            .iter()
+            .map(|segment| segment.content.clone())
+            .collect::<Vec<_>>(),
+        vec![
+            BlockContent::Markdown("# Owned\n".into()),
+            BlockContent::Code("\nfn owned() {}\n".into()),
+        ]
+    );
+    assert_eq!(
+        blocks[1].info.metadata["owner"],
+        serde_json::json!({"name": "bob@example.com"})
+    );
+    assert_eq!(
+        blocks[1].content[0].content,
+        BlockContent::Markdown("\nProse\n".into())
+    );
+}
+
+#[test]
+fn test_decorated_comments() {
+    let config = LanguageConfig {
+        decoration_prefixes: vec!["*".to_string()],
+        ..rust_config()
+    };
+    let source = "/*@[Decorated|Impl]\n * # Decorated\n *\n *   indented {Target}\n */\n    fn kept() {}\n/**\n * More\n */\n/*@*/";
+    assert_eq!(
+        block_contents(source, &config),
+        vec![
+            BlockContent::Markdown("\n# Decorated\n\n  indented ".into()),
+            BlockContent::Link(BlockLink {
+                target: "Target".to_string(),
+                heading: None,
+                label: None,
+            }),
+            BlockContent::Markdown("\n".into()),
+            BlockContent::Code("\n    fn kept() {}\n".into()),
+            BlockContent::Markdown("\nMore\n".into()),
+            BlockContent::Code("\n".into()),
+        ]
+    );
+
+    let source = "/*@[Indented|Impl]\n    * not a decoration\n    **bold**\n*/\n/*@*/";
+    assert_eq!(
+        block_contents(source, &config),
+        vec![
+            BlockContent::Markdown("\n* not a decoration\n**bold**\n".into()),
+            BlockContent::Code("\n".into()),
+        ]
+    );
+}
+
+#[test]
+fn test_segments_borrow_from_source() {
+    let source = "/*@[Borrowed|Impl]\n# Borrowed\n*/\nfn code() {}\n/*\nescaped \\@\n*/\n/*@*/\n\
+                  //@[Lines|Impl]\n// prose\nlet a = 1;\nlet b = 2;\n//@\n";
+    let config = rust_config();
+    let source_index = SourceIndex::new(Path::new("test"), source);
+    let (_, (blocks, _)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+
+    let borrowed: Vec<bool> = blocks
+        .iter()
+        .flat_map(|block| &block.content)
+        .map(|segment| match &segment.content {
+            BlockContent::Markdown(text) | BlockContent::Code(text) => {
+                matches!(text, Cow::Borrowed(_))
+            }
+            _ => false,
+        })
+        .collect();
+    // Markdown merged from several comment lines has to be copied
+    assert_eq!(borrowed, vec![true, true, false, true, false, true]);
+    assert_eq!(
+        blocks[1].content[1].content,
+        BlockContent::Code("let a = 1;\nlet b = 2;\n".into())
+    );
+}
+
+#[test]
+fn test_whole_file_blocks() {
+    let config = LanguageConfig {
+        language: "markdown".to_string(),
+        anubis_character: "@".to_string(),
+        multiline_start: "<!--".to_string(),
+        multiline_end: "-->".to_string(),
+        whole_file: Some(WholeFile {
+            template: "Guide".to_string(),
+        }),
+        ..Default::default()
+    };
+    let source = "```rust\n# not a heading\nlet map = { };\n```\n\n## Getting Started\nSee {Parser} and mail me@example.com.\n\n{{Example}}\n";
+    let source_index = SourceIndex::new(Path::new("docs/guide.md"), source);
+    let (_, (blocks, diagnostics)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+    assert!(diagnostics.is_empty());
+    assert_eq!(blocks.len(), 1);
+    let block = &blocks[0];
+    assert_eq!(block.info.name, "Getting Started");
+    assert_eq!(block.info.template_name, "Guide");
+    assert_eq!(block.info.location.span.start_line, 1);
+    let content: Vec<_> = block
+        .content
+        .iter()
+        .map(|segment| &segment.content)
+        .collect();
+    assert_eq!(
+        content,
+        vec![
+            &BlockContent::Markdown(
+                "```rust\n# not a heading\nlet map = { };\n```\n\n## Getting Started\nSee ".into()
+            ),
+            &BlockContent::Link(BlockLink::parse("Parser")),
+            &BlockContent::Markdown(" and mail me@example.com.\n\n".into()),
+            &BlockContent::Embed("Example".to_string()),
+            &BlockContent::Markdown("\n".into()),
+        ]
+    );
+
+    let source = "---\ntitle: Install\ntags: [setup]\n---\n# Installing\n";
+    let source_index = SourceIndex::new(Path::new("docs/install.md"), source);
+    let (_, (blocks, _)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+    assert_eq!(blocks[0].info.name, "Install");
+    assert_eq!(blocks[0].info.metadata["tags"][0], "setup");
+    assert_eq!(
+        blocks[0].content[0].content,
+        BlockContent::Markdown("# Installing\n".into())
+    );
+
+    let source = "Just some notes.\n";
+    let source_index = SourceIndex::new(Path::new("docs/notes.md"), source);
+    let (_, (blocks, _)) = file_parser(&config, &[], &source_index)
+        .parse(source)
+        .unwrap();
+    assert_eq!(blocks[0].info.name, "notes");
+}
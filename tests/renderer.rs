@@ -1 +1,279 @@
+use anubis::{
+    common::{Anubis, Block, BlockContent, BlockInfo, BlockLink, BlockSegment, SourceLocation},
+    config::{AnubisConfig, LanguageConfig},
+    db::AnubisDatabase,
+    diagnostics::Severity,
+    extension::{ContentExtension, CustomContent},
+    file_embed::FileEmbed,
+    renderer::AnubisRenderer,
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+use tera::Tera;
 
+fn test_anubis(templates: Vec<(&str, &str)>) -> Anubis {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(templates).unwrap();
+    Anubis {
+        config: AnubisConfig::default(),
+        database: AnubisDatabase::default(),
+        tera,
+        extensions: vec![],
+    }
+}
+
+#[test]
+fn test_apply_template_arguments() {
+    let anubis = test_anubis(vec![(
+        "FunctionTemplate.html",
+        "fn({{ args.n }}) -> {{ return }} {{ html | safe }}",
+    )]);
+    let info = BlockInfo {
+        name: "Factorial Function".to_string(),
+        template_name: "FunctionTemplate".to_string(),
+        args: serde_json::from_str(r#"{"args": {"n": "u32"}, "return": "u32", "html": "ignored"}"#)
+            .unwrap(),
+        parent: None,
+        metadata: Default::default(),
+        location: SourceLocation::default(),
+        continuation: false,
+        fragments: vec![],
+        signature: None,
+    };
+
+    let rendered = anubis
+        .apply_template("<p>body</p>", &HashSet::new(), &info)
+        .unwrap();
+    assert_eq!(rendered, "fn(u32) -> u32 <p>body</p>");
+}
+
+fn markdown_block(name: &str, content: Vec<BlockContent<'static>>) -> Block<'static> {
+    Block {
+        info: BlockInfo {
+            name: name.to_string(),
+            template_name: "Impl".to_string(),
+            args: Default::default(),
+            parent: None,
+            metadata: Default::default(),
+            location: SourceLocation::default(),
+            continuation: false,
+            fragments: vec![],
+            signature: None,
+        },
+        content: content
+            .into_iter()
+            .map(|content| BlockSegment {
+                content,
+                span: Default::default(),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_render_link_with_heading_and_label() {
+    let mut anubis = test_anubis(vec![]);
+    anubis.config.url = "http://127.0.0.1:3000/".to_string();
+
+    let rendered = anubis.render_link(
+        &BlockLink::parse("Guide#Getting Started!|start here"),
+        Path::new("src/lib.rs"),
+    );
+    assert_eq!(
+        rendered,
+        "<p><a href=\"http://127.0.0.1:3000/Guide#headergetting-started\">start here</a></p>\n"
+    );
+
+    // Links to known blocks use their qualified id
+    let mut guide = markdown_block("Guide", vec![]);
+    guide.info.location.path = "src/guide.rs".into();
+    anubis
+        .database
+        .insert_blocks(vec![guide], &LanguageConfig::default());
+    let rendered = anubis.render_link(&BlockLink::parse("Guide"), Path::new("src/lib.rs"));
+    assert_eq!(
+        rendered,
+        "<p><a href=\"http://127.0.0.1:3000/src/guide.rs::Guide\">Guide</a></p>\n"
+    );
+}
+
+#[test]
+fn test_heading_anchors_match_rendered_ids() {
+    let block = markdown_block(
+        "Guide",
+        vec![BlockContent::Markdown(
+            "# Getting Started!\n## Usage\n## Usage\n".into(),
+        )],
+    );
+    let anchors = Anubis::heading_anchors(&block);
+    assert_eq!(anchors, vec!["getting-started", "usage", "usage-1"]);
+
+    let html = Anubis::render_markdown("# Getting Started!\n");
+    assert!(html.contains("id=\"headergetting-started\""));
+}
+
+#[test]
+fn test_render_page_with_and_without_metadata() {
+    let mut anubis = test_anubis(vec![]);
+    anubis
+        .tera
+        .add_template_file("default_templates/page.html", Some("page.html"))
+        .unwrap();
+    let mut tagged = markdown_block("Tagged", vec![]);
+    tagged.info.metadata = serde_json::from_str(r#"{"tags": ["core"]}"#).unwrap();
+    anubis.database.insert_blocks(
+        vec![markdown_block("Plain", vec![]), tagged],
+        &LanguageConfig::default(),
+    );
+    for name in ["Plain", "Tagged"] {
+        anubis
+            .database
+            .html_db
+            .insert(name.to_string(), format!("<p>{name}</p>"));
+    }
+
+    let render = |name| {
+        let context = anubis.database.get_context(name).unwrap();
+        anubis.tera.render("page.html", &context).unwrap()
+    };
+    let plain = render("Plain");
+    assert!(plain.contains("<p>Plain</p>"));
+    assert!(!plain.contains("class=\"tags\""));
+    assert!(render("Tagged").contains("<span class=\"tag\">core</span>"));
+}
+
+#[test]
+fn test_page_context_arguments() {
+    let mut anubis = test_anubis(vec![]);
+    let mut block = markdown_block("Factorial Function", vec![]);
+    block.info.args =
+        serde_json::from_str(r#"{"args": {"n": "u32"}, "return": "u32", "html": "ignored"}"#)
+            .unwrap();
+    anubis
+        .database
+        .insert_blocks(vec![block], &LanguageConfig::default());
+    anubis
+        .database
+        .html_db
+        .insert("Factorial Function".to_string(), "<p>body</p>".to_string());
+
+    // Pages get the same arguments as the block's own template
+    let context = anubis.database.get_context("Factorial Function").unwrap();
+    let rendered = Tera::one_off(
+        "fn({{ args.n }}) -> {{ return }} {{ html | safe }}",
+        &context,
+        false,
+    )
+    .unwrap();
+    assert_eq!(rendered, "fn(u32) -> u32 <p>body</p>");
+}
+
+#[test]
+fn test_check_links_reports_missing_headings() {
+    let mut anubis = test_anubis(vec![]);
+    let lang = LanguageConfig::default();
+    anubis.database.insert_blocks(
+        vec![
+            markdown_block("Guide", vec![BlockContent::Markdown("# Usage\n".into())]),
+            markdown_block(
+                "Index",
+                vec![
+                    BlockContent::Link(BlockLink::parse("Guide#Usage")),
+                    BlockContent::Link(BlockLink::parse("Guide#Missing")),
+                    BlockContent::Link(BlockLink::parse("Nowhere")),
+                ],
+            ),
+        ],
+        &lang,
+    );
+
+    let mut diagnostics = anubis.check_links();
+    diagnostics.sort_by(|a, b| a.message.cmp(&b.message));
+    let messages: Vec<(Severity, &str)> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (Severity::Error, "block `Guide` has no heading `Missing`"),
+            (Severity::Warning, "link to unknown block `Nowhere`"),
+        ]
+    );
+}
+
+#[test]
+fn test_render_file_embed() {
+    let path = PathBuf::from(format!("target/anubis-render-{}.rs", std::process::id()));
+    std::fs::write(
+        &path,
+        "fn main() {}\n// #region body\nlet a = 1;\n// #endregion\n",
+    )
+    .unwrap();
+    let mut anubis = test_anubis(vec![]);
+    anubis.config.language_configs.insert(
+        "rs".to_string(),
+        LanguageConfig {
+            language: "rust".to_string(),
+            ..Default::default()
+        },
+    );
+
+    let file = FileEmbed::parse(&format!("{}#region=body", path.display())).unwrap();
+    assert_eq!(
+        anubis.render_file(&file).unwrap(),
+        "<pre><code class=\"language-rust\">let a = 1;\n</code></pre>\n"
+    );
+
+    let missing = FileEmbed::parse(&format!("{}#region=missing", path.display())).unwrap();
+    assert_eq!(
+        anubis.render_file(&missing).unwrap_err().to_string(),
+        "no region `missing`"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+struct IssueExtension;
+
+impl ContentExtension for IssueExtension {
+    fn name(&self) -> &str {
+        "issue"
+    }
+
+    fn start(&self) -> &str {
+        "{!"
+    }
+
+    fn parse(&self, text: &str) -> Option<serde_json::Value> {
+        Some(serde_json::json!({ "number": text.strip_prefix("issue ")?.parse::<u64>().ok()? }))
+    }
+
+    fn render(
+        &self,
+        data: &serde_json::Value,
+        _anubis: &Anubis,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(format!("<a href=\"/issues/{0}\">#{0}</a>", data["number"]))
+    }
+}
+
+#[test]
+fn test_render_custom_content() {
+    let mut anubis = test_anubis(vec![]);
+    let custom = CustomContent {
+        extension: "issue".to_string(),
+        data: serde_json::json!({ "number": 12 }),
+    };
+    assert_eq!(
+        anubis.render_custom(&custom).unwrap_err().to_string(),
+        "No extension called `issue` is registered"
+    );
+
+    anubis.register_extension(IssueExtension);
+    assert_eq!(
+        anubis.render_custom(&custom).unwrap(),
+        "<a href=\"/issues/12\">#12</a>"
+    );
+}
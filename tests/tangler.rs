@@ -0,0 +1,117 @@
+use anubis::{
+    common::Anubis,
+    config::{AnubisConfig, LanguageConfig},
+    db::AnubisDatabase,
+    parser::AnubisParser,
+    tangler::AnubisTangler,
+};
+use std::{collections::HashSet, fs, path::PathBuf};
+use tera::Tera;
+
+fn sql_anubis() -> Anubis {
+    let language_config = LanguageConfig {
+        language: "sql".to_string(),
+        anubis_character: "@".to_string(),
+        single_line_prefixes: vec!["--".to_string()],
+        ..Default::default()
+    };
+    Anubis {
+        config: AnubisConfig {
+            language_configs: [("sql".to_string(), language_config)].into(),
+            ..Default::default()
+        },
+        database: AnubisDatabase::default(),
+        tera: Tera::default(),
+        extensions: vec![],
+    }
+}
+
+#[test]
+fn test_tangle() {
+    let directory = std::env::temp_dir().join(format!("anubis-tangle-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    // Tangled files have to be inside the project, which tests run from
+    let output_directory = PathBuf::from(format!("target/anubis-tangle-{}", std::process::id()));
+    let output = output_directory.join("out/schema.sql");
+    let source = directory.join("schema.sql");
+    fs::write(
+        &source,
+        format!(
+            "--@[Indexes|Impl {{file: \"{0}\", order: 2}}]\n-- Indexes come last\nCREATE INDEX users_name ON users (name);\n--@\n--@[Tables|Impl {{file: \"{0}\", order: 1}}]\n-- Tables are created first, users before orders\n-- {{{{Users}}}}\nCREATE TABLE orders (\n--@[Order Columns|Impl]\n-- Each order belongs to a user\n  user_id INTEGER REFERENCES users\n--@\n);\n--@\n--@[Users|Impl]\nCREATE TABLE users (name TEXT);\n--@\n",
+            output.display()
+        ),
+    )
+    .unwrap();
+    let files: HashSet<PathBuf> = [source.clone()].into();
+
+    let mut anubis = sql_anubis();
+    assert!(anubis
+        .parse_files(files.clone())
+        .unwrap()
+        .diagnostics
+        .is_empty());
+    let expected = "CREATE TABLE users (name TEXT);\nCREATE TABLE orders (\n  user_id INTEGER REFERENCES users\n);\nCREATE INDEX users_name ON users (name);\n";
+    let summary = anubis.tangle().unwrap();
+    assert!(summary.diagnostics.is_empty());
+    assert_eq!(summary.files[&output], expected);
+    assert_eq!(summary.to_string(), "Tangled 1 files");
+    assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+
+    // Unknown and cyclic embeds are reported and nothing is written
+    fs::write(
+        &source,
+        format!(
+            "--@[Root|Impl {{file: \"{}\"}}]\n-- {{{{Missing}}}}\n-- {{{{Loop}}}}\n--@\n--@[Loop|Impl]\n-- {{{{Back}}}}\n--@\n--@[Back|Impl]\n-- {{{{Loop}}}}\n--@\n",
+            output.display()
+        ),
+    )
+    .unwrap();
+    anubis.parse_files(files.clone()).unwrap();
+    let summary = anubis.tangle().unwrap();
+    let messages: Vec<_> = summary
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            "block `Root` embeds unknown block `Missing`",
+            "cyclic embed: Root -> Loop -> Back -> Loop",
+        ]
+    );
+    assert_eq!(summary.diagnostics[0].location.span.start_line, 2);
+    assert_eq!(summary.diagnostics[0].snippet, "-- {{Missing}}");
+    assert_eq!(summary.to_string(), "Tangled no files, 2 error(s) found");
+    assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+
+    // Files outside the project are reported instead of written
+    let outside = directory.join("outside.sql");
+    fs::write(
+        &source,
+        format!(
+            "--@[Absolute|Impl {{file: \"{}\"}}]\nSELECT 1;\n--@\n--@[Parent|Impl {{file: \"out/../../outside.sql\"}}]\nSELECT 2;\n--@\n",
+            outside.display()
+        ),
+    )
+    .unwrap();
+    anubis.parse_files(files).unwrap();
+    let summary = anubis.tangle().unwrap();
+    let mut messages: Vec<_> = summary
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect();
+    messages.sort();
+    assert_eq!(
+        messages,
+        vec![
+            "the `file` of block `Absolute` isn't a path inside the project",
+            "the `file` of block `Parent` isn't a path inside the project",
+        ]
+    );
+    assert!(!outside.exists());
+
+    fs::remove_dir_all(&output_directory).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,90 @@
+use anubis::{
+    common::Anubis,
+    config::{AnubisConfig, LanguageConfig},
+    db::AnubisDatabase,
+    parser::AnubisParser,
+    tester::AnubisTester,
+};
+use std::{collections::HashSet, fs, path::PathBuf};
+use tera::Tera;
+
+fn shell_anubis(test_command: Option<&str>) -> Anubis {
+    let language_config = LanguageConfig {
+        language: "shell".to_string(),
+        anubis_character: "@".to_string(),
+        single_line_prefixes: vec!["#".to_string()],
+        test_command: test_command.map(str::to_string),
+        ..Default::default()
+    };
+    Anubis {
+        config: AnubisConfig {
+            language_configs: [("sh".to_string(), language_config)].into(),
+            ..Default::default()
+        },
+        database: AnubisDatabase::default(),
+        tera: Tera::default(),
+        extensions: vec![],
+    }
+}
+
+#[test]
+fn test_doc_tests() {
+    let directory = std::env::temp_dir().join(format!("anubis-doc-tests-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    let source = directory.join("checks.sh");
+    fs::write(
+        &source,
+        "#@[Passing|Impl]\n# ---\n# test: true\n# ---\n# Runs the nested check\nstatus=0\n#@[Nested|Impl]\n# Sets the status\nstatus=$((status + 3))\n#@\n[ $status -eq 3 ]\n#@\n#@[Failing|Impl]\n# ---\n# test: true\n# ---\necho broken; exit 4\n#@\n#@[Untested|Impl]\nexit 1\n#@\n",
+    )
+    .unwrap();
+    let files: HashSet<PathBuf> = [source].into();
+
+    let mut anubis = shell_anubis(Some("sh {file}"));
+    let summary = anubis.parse_files(files.clone()).unwrap();
+    assert!(summary.diagnostics.is_empty());
+    let summary = anubis.test_blocks().unwrap();
+    assert_eq!(summary.results.len(), 2);
+    assert!(summary.results["Passing"].passed);
+    assert!(!summary.results["Failing"].passed);
+    assert_eq!(summary.failed(), 1);
+    assert_eq!(
+        summary.to_string(),
+        "test `Failing` failed:\nbroken\n1 passed, 1 failed"
+    );
+    anubis
+        .database
+        .insert_html("Failing".to_string(), String::new());
+    let context = anubis.database.get_context("Failing").unwrap();
+    assert_eq!(context.get("test").unwrap()["passed"], false);
+
+    // Results are dropped when the block is parsed again
+    fs::write(
+        directory.join("checks.sh"),
+        "#@[Failing|Impl]\nexit 0\n#@\n",
+    )
+    .unwrap();
+    anubis.parse_files(files).unwrap();
+    assert!(anubis.database.test_db.is_empty());
+
+    let mut anubis = shell_anubis(None);
+    anubis
+        .parse_files([directory.join("checks.sh")].into())
+        .unwrap();
+    anubis
+        .database
+        .block_db
+        .get_mut("Failing")
+        .unwrap()
+        .info
+        .metadata = serde_json::from_str(r#"{"test": true}"#).unwrap();
+    let summary = anubis.test_blocks().unwrap();
+    assert_eq!(
+        summary.results["Failing"].output,
+        format!(
+            "no `test_command` is configured for {}",
+            directory.join("checks.sh").display()
+        )
+    );
+
+    fs::remove_dir_all(&directory).unwrap();
+}